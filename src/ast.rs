@@ -1,30 +1,139 @@
+use std::borrow::Cow;
 use std::fmt::{Debug, Error, Formatter};
 
+use file_data::FileHandle;
 use lexer::Location;
 
+/// The items from one file, each file is a namespace of its own
+pub struct Module<'a> {
+    pub file: FileHandle,
+    /// The name other modules call this module's functions with
+    pub name: String,
+    pub imports: Vec<FileHandle>,
+    /// Public functions in the prelude can be called from any module without naming the module
+    pub prelude: bool,
+    pub items: Vec<Item<'a>>,
+}
+
+pub enum Item<'a> {
+    Function(Function<'a>),
+    Global(Global<'a>),
+}
+
+/// A top level `const` or `global` declaration, located at its name
+pub struct Global<'a> {
+    pub name: &'a str,
+    pub mutable: bool,
+    pub expr: Expr<'a>,
+    pub start: Location,
+    pub end: Location,
+}
+
+impl<'a> Global<'a> {
+    pub fn new(
+        start: Location,
+        name: &'a str,
+        end: Location,
+        expr: Expr<'a>,
+        mutable: bool,
+    ) -> Global<'a> {
+        Global {
+            name,
+            mutable,
+            expr,
+            start,
+            end,
+        }
+    }
+}
+
 pub struct Function<'a> {
     pub name: String,
     pub arguments: Vec<&'a str>,
+    /// The location of each argument's name
+    pub argument_sites: Vec<(Location, Location)>,
     pub stmts: Vec<Statement<'a>>,
+    /// Whether it can be called from other modules
+    pub public: bool,
+    pub name_start: Location,
+    pub name_end: Location,
+    /// The signature, from the start of the definition to the end of the arguments
+    pub start: Location,
+    pub end: Location,
 }
 
 impl<'a> Function<'a> {
-    pub fn new(name: &str, arguments: Vec<&'a str>, stmts: Vec<Statement<'a>>) -> Function<'a> {
+    pub fn new(
+        start: Location,
+        public: bool,
+        (name_start, name, name_end): (Location, &str, Location),
+        arguments: Vec<(Location, &'a str, Location)>,
+        end: Location,
+        stmts: Vec<Statement<'a>>,
+    ) -> Function<'a> {
         Function {
             name: name.into(),
-            arguments,
+            arguments: arguments.iter().map(|&(_, name, _)| name).collect(),
+            argument_sites: arguments
+                .iter()
+                .map(|&(start, _, end)| (start, end))
+                .collect(),
             stmts,
+            public,
+            name_start,
+            name_end,
+            start,
+            end,
         }
     }
+
+    /// Whether any part of the function had to be recovered from a parse error
+    pub fn has_errors(&self) -> bool {
+        self.stmts.iter().any(Statement::has_errors)
+    }
 }
 
 pub enum Statement<'a> {
     Expr(Expr<'a>),
     Return(Expr<'a>),
     Assign(Expr<'a>, Expr<'a>),
-    Declare(&'a str, Expr<'a>),
-    If(Expr<'a>, Vec<Statement<'a>>),
+    /// Only created for operators with an integer form
+    CompoundAssign(Expr<'a>, BinaryOpCode, Expr<'a>),
+    /// The locations are those of the variable's name
+    Declare(Location, &'a str, Location, Expr<'a>),
+    /// An empty else block when there's no else
+    If(Expr<'a>, Vec<Statement<'a>>, Vec<Statement<'a>>),
     While(Expr<'a>, Vec<Statement<'a>>),
+    /// `for variable in start..end`
+    ForRange(&'a str, Expr<'a>, Expr<'a>, Vec<Statement<'a>>),
+    /// `for variable in array`
+    ForEach(&'a str, Expr<'a>, Vec<Statement<'a>>),
+    Break(Location, Location),
+    Continue(Location, Location),
+}
+
+impl<'a> Statement<'a> {
+    fn has_errors(&self) -> bool {
+        use self::Statement::*;
+        match *self {
+            Expr(ref expr) | Return(ref expr) | Declare(_, _, _, ref expr) => expr.is_error(),
+            Assign(ref target, ref expr) | CompoundAssign(ref target, _, ref expr) => {
+                target.is_error() || expr.is_error()
+            }
+            If(ref test, ref block, ref else_block) => {
+                test.is_error()
+                    || block.iter().any(Statement::has_errors)
+                    || else_block.iter().any(Statement::has_errors)
+            }
+            While(ref test, ref block) | ForEach(_, ref test, ref block) => {
+                test.is_error() || block.iter().any(Statement::has_errors)
+            }
+            ForRange(_, ref start, ref end, ref block) => {
+                start.is_error() || end.is_error() || block.iter().any(Statement::has_errors)
+            }
+            Break(..) | Continue(..) => false,
+        }
+    }
 }
 
 pub struct Expr<'a> {
@@ -36,11 +145,12 @@ pub struct Expr<'a> {
 pub enum ExprKind<'a> {
     Number(i32),
     Char(char),
-    String(&'a str),
+    String(Cow<'a, str>),
     Array(Vec<Expr<'a>>),
     BinaryOp(Box<Expr<'a>>, BinaryOpCode, Box<Expr<'a>>),
     UnaryOp(UnaryOpCode, Box<Expr<'a>>),
-    Call(&'a str, Vec<Expr<'a>>),
+    /// The module is given for qualified calls like `module::function()`
+    Call(Option<&'a str>, &'a str, Vec<Expr<'a>>),
     Identifier(&'a str),
     Subscription(Box<Expr<'a>>, Box<Expr<'a>>),
     Error,
@@ -63,11 +173,23 @@ impl<'a> Expr<'a> {
     cons! {new_unary_op(op: UnaryOpCode, expr: Self) => UnaryOp}
     cons! {new_subscription(array_expr: Self, subscript_expr: Self) => Subscription}
     cons! {new_number(n: i32) => Number}
-    cons! {new_string(s: &'a str) => String}
+    cons! {new_string(s: Cow<'a, str>) => String}
     cons! {new_char(c: char) => Char}
     cons! {new_array(exprs: Vec<Self>) => Array}
-    cons! {new_call(func: &'a str, exprs: Vec<Self>) => Call}
+    cons! {new_call(module: Option<&'a str>, func: &'a str, exprs: Vec<Self>) => Call}
     cons! {new_identifier(name: &'a str) => Identifier}
+
+    pub fn new_error(start: Location, end: Location) -> Self {
+        Self {
+            kind: ExprKind::Error,
+            start,
+            end,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        matches!(self.kind, ExprKind::Error)
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -117,9 +239,28 @@ fn write_id_list(fmt: &mut Formatter, ids: &[&str]) -> Result<(), Error> {
     fmt.write_char(']')
 }
 
+impl<'a> Debug for Item<'a> {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        match *self {
+            Item::Function(ref func) => func.fmt(fmt),
+            Item::Global(ref global) => global.fmt(fmt),
+        }
+    }
+}
+
+impl<'a> Debug for Global<'a> {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        let kind = if self.mutable { "Global" } else { "Const" };
+        write!(fmt, "{}(name: {}, expr: {:?})", kind, self.name, self.expr)
+    }
+}
+
 impl<'a> Debug for Function<'a> {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
         //write!(fmt, "Function(name: {}, arguments: {:?}, stmts: {:?})", self.name, self.arguments, self.stmts)
+        if self.public {
+            write!(fmt, "pub ")?;
+        }
         write!(fmt, "Function(name: {}, arguments: ", self.name)?;
         write_id_list(fmt, &self.arguments)?;
         write!(fmt, ", stmts: {:?})", self.stmts)
@@ -135,13 +276,36 @@ impl<'a> Debug for Statement<'a> {
             Assign(ref target, ref expr) => {
                 write!(fmt, "Assign(target: {:?}, expr: {:?})", target, expr)
             }
-            Declare(ref id, ref expr) => {
+            CompoundAssign(ref target, op, ref expr) => write!(
+                fmt,
+                "CompoundAssign(target: {:?}, op: {:?}, expr: {:?})",
+                target, op, expr
+            ),
+            Declare(_, ref id, _, ref expr) => {
                 write!(fmt, "Declare(identifier: {}, expr: {:?})", id, expr)
             }
-            If(ref test, ref block) => write!(fmt, "If(test: {:?}, block: {:?})", test, block),
+            If(ref test, ref block, ref else_block) => {
+                write!(fmt, "If(test: {:?}, block: {:?}", test, block)?;
+                if !else_block.is_empty() {
+                    write!(fmt, ", else: {:?}", else_block)?;
+                }
+                write!(fmt, ")")
+            }
             While(ref test, ref block) => {
                 write!(fmt, "While(test: {:?}, block: {:?})", test, block)
             }
+            ForRange(var, ref start, ref end, ref block) => write!(
+                fmt,
+                "ForRange(variable: {}, start: {:?}, end: {:?}, block: {:?})",
+                var, start, end, block
+            ),
+            ForEach(var, ref array, ref block) => write!(
+                fmt,
+                "ForEach(variable: {}, array: {:?}, block: {:?})",
+                var, array, block
+            ),
+            Break(..) => write!(fmt, "Break"),
+            Continue(..) => write!(fmt, "Continue"),
         }
     }
 }
@@ -162,7 +326,12 @@ impl<'a> Debug for ExprKind<'a> {
             Array(ref exprs) => write!(fmt, "Array({:?})", exprs),
             BinaryOp(ref l, op, ref r) => write!(fmt, "BinaryOp({:?} {:?} {:?})", l, op, r),
             UnaryOp(op, ref expr) => write!(fmt, "UnaryOp({:?} {:?})", op, expr),
-            Call(ref func, ref args) => {
+            Call(Some(module), func, ref args) => write!(
+                fmt,
+                "Call(function: {}::{}, arguments: {:?})",
+                module, func, args
+            ),
+            Call(None, func, ref args) => {
                 write!(fmt, "Call(function: {}, arguments: {:?})", func, args)
             }
             Identifier(ref name) => write!(fmt, "Identifier({})", name),
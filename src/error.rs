@@ -1,15 +1,18 @@
-use ansi_term::Colour::Red;
+use ansi_term::Colour::{Red, Yellow};
 use exec_tree::error::*;
+use exec_tree::lints::{LintLevel, LintWarning};
 use lalrpop_util;
 use lexer::{self, Location, Tok};
 use std;
 use std::io::{self, Write};
 
 use file_data::FileData;
+use imports::ImportError;
 
 #[derive(Debug)]
 pub enum OuterError {
     ReadInput,
+    ImportFailed,
     ParseError,
     StaticAnalysisFailed,
     RuntimeFailure,
@@ -38,12 +41,63 @@ macro_rules! error {
     };
 }
 
+macro_rules! warning {
+    ( $out:expr, $fmt:expr, $( $arg:expr ),* ) => {
+        writeln!($out, concat!("{}: ", $fmt), Yellow.paint("warning"), $( $arg, )*)
+    };
+}
+
 pub fn write_parse_error(
     f: &mut dyn Write,
-    err: ParseError,
+    errs: Vec<ParseError>,
     contents: &FileData,
 ) -> OuterResult<()> {
-    write_parse_error_inner(f, err, contents).map_err(|_| OuterError::OutputError)
+    for (i, err) in errs.into_iter().enumerate() {
+        if i > 0 {
+            writeln!(f).map_err(|_| OuterError::OutputError)?;
+        }
+        write_parse_error_inner(f, err, contents).map_err(|_| OuterError::OutputError)?;
+    }
+    Ok(())
+}
+
+pub fn write_import_errors(
+    f: &mut dyn Write,
+    errs: &[ImportError],
+    contents: &FileData,
+) -> OuterResult<()> {
+    for (i, err) in errs.iter().enumerate() {
+        if i > 0 {
+            writeln!(f).map_err(|_| OuterError::OutputError)?;
+        }
+        write_import_error(f, err, contents).map_err(|_| OuterError::OutputError)?;
+    }
+    Ok(())
+}
+
+fn write_import_error(f: &mut dyn Write, err: &ImportError, contents: &FileData) -> io::Result<()> {
+    match *err {
+        ImportError::NotFound(ref path, ref tried, start, end) => {
+            error!(f, "Couldn't find import {:?}", path)?;
+            write_locations(f, &start, &end, contents)?;
+            writeln!(f, "Looked for:")?;
+            for candidate in tried {
+                writeln!(f, "    {}", candidate)?;
+            }
+        }
+        ImportError::Cycle(ref path, start, end) => {
+            error!(f, "Import of {:?} leads back to this file", path)?;
+            write_locations(f, &start, &end, contents)?;
+        }
+        ImportError::NameClash(ref path, start, end) => {
+            error!(
+                f,
+                "Import of {:?} has the same module name as an earlier import", path
+            )?;
+            write_locations(f, &start, &end, contents)?;
+        }
+    }
+    Ok(())
 }
 
 fn write_parse_error_inner(
@@ -115,10 +169,11 @@ fn write_exec_error_inner<'a>(
             }
         }
         ExecError::RuntimeFailure(ref kind, ref stack) => {
-            writeln!(f, "{}", kind)?;
-            for site in stack {
-                write_locations(f, &site.start, &site.end, contents)?;
+            writeln!(f, "Traceback (innermost frame last):")?;
+            for frame in stack.iter().rev() {
+                write_stack_frame(f, frame, contents)?;
             }
+            writeln!(f, "{}", kind)?;
         }
     }
     Ok(())
@@ -130,14 +185,204 @@ fn write_static_analysis_err<'a>(
     contents: &FileData,
 ) -> io::Result<()> {
     match *err {
-        StaticAnalysisError::CallUnknownFunction(ref fname, start, end) => {
+        StaticAnalysisError::CallUnknownFunction(ref fname, ref suggestion, start, end) => {
             error!(f, "Call to unknown function {:?}", fname)?;
             write_locations(f, &start, &end, contents)?;
+            write_suggestion(f, suggestion)?;
+        }
+        StaticAnalysisError::WrongArgumentCount(ref fname, ref parameters, given, start, end) => {
+            error!(
+                f,
+                "Function {:?} takes {} argument{} but {} {} given",
+                fname,
+                parameters.len(),
+                if parameters.len() == 1 { "" } else { "s" },
+                given,
+                if given == 1 { "was" } else { "were" }
+            )?;
+            write_locations(f, &start, &end, contents)?;
+            writeln!(f, "Expected: {}({})", fname, parameters.join(", "))?;
+        }
+        StaticAnalysisError::DuplicateFunction(ref fname, first_start, first_end, start, end) => {
+            error!(f, "Function {:?} is defined more than once", fname)?;
+            write_locations(f, &start, &end, contents)?;
+            writeln!(f, "First defined:")?;
+            write_locations(f, &first_start, &first_end, contents)?;
+        }
+        StaticAnalysisError::CallPrivateFunction(ref fname, start, end) => {
+            error!(
+                f,
+                "Function {:?} is private to its module, mark it pub to call it from other modules",
+                fname
+            )?;
+            write_locations(f, &start, &end, contents)?;
+        }
+        StaticAnalysisError::UnknownModule(ref module, start, end) => {
+            error!(
+                f,
+                "Unknown module {:?}, modules must be imported before their functions can be called",
+                module
+            )?;
+            write_locations(f, &start, &end, contents)?;
+        }
+        StaticAnalysisError::BreakOutsideLoop(start, end) => {
+            error!(f, "break used outside of a loop")?;
+            write_locations(f, &start, &end, contents)?;
+        }
+        StaticAnalysisError::ContinueOutsideLoop(start, end) => {
+            error!(f, "continue used outside of a loop")?;
+            write_locations(f, &start, &end, contents)?;
+        }
+        StaticAnalysisError::ExpectedIntGotArray(start, end) => {
+            error!(f, "Expected int got an array, this will always fail")?;
+            write_locations(f, &start, &end, contents)?;
+        }
+        StaticAnalysisError::ExpectedArrayGotInt(start, end) => {
+            error!(f, "Expected array got an int, this will always fail")?;
+            write_locations(f, &start, &end, contents)?;
+        }
+        StaticAnalysisError::UndeclaredVariable(ref name, ref suggestion, start, end) => {
+            error!(f, "Use of undeclared variable {:?}", name)?;
+            write_locations(f, &start, &end, contents)?;
+            write_suggestion(f, suggestion)?;
+        }
+        StaticAnalysisError::AssignToUndeclaredVariable(ref name, ref suggestion, start, end) => {
+            error!(
+                f,
+                "Assignment to undeclared variable {:?}, declare it with let first", name
+            )?;
+            write_locations(f, &start, &end, contents)?;
+            write_suggestion(f, suggestion)?;
+        }
+        StaticAnalysisError::MissingEntryPoint(ref name, file) => {
+            error!(
+                f,
+                "No function {:?} defined in {} to start from",
+                name,
+                contents.get_name(file)
+            )?;
+        }
+        StaticAnalysisError::EntryPointParameters(ref name, count, start, end) => {
+            error!(
+                f,
+                "Function {:?} takes {} parameters but can take at most one, the command line arguments",
+                name,
+                count
+            )?;
+            write_locations(f, &start, &end, contents)?;
+        }
+        StaticAnalysisError::InvalidAssignmentTarget(start, end) => {
+            error!(f, "Can only assign to a variable or an array element")?;
+            write_locations(f, &start, &end, contents)?;
+        }
+        StaticAnalysisError::DeniedLint(ref warning) => {
+            write_lint_warning(f, warning, LintLevel::Deny, contents)?;
+        }
+        StaticAnalysisError::GlobalReadBeforeInitialised(ref name, start, end) => {
+            error!(
+                f,
+                "Global {:?} read in an initialiser before it has been set", name
+            )?;
+            write_locations(f, &start, &end, contents)?;
+        }
+        StaticAnalysisError::CallReadsGlobalBeforeInitialised(ref fname, ref name, start, end) => {
+            error!(
+                f,
+                "Function {:?} can read global {:?} before it has been set, so it can't be called \
+                 from this initialiser",
+                fname,
+                name
+            )?;
+            write_locations(f, &start, &end, contents)?;
+        }
+        StaticAnalysisError::DuplicateGlobal(ref name, first_start, first_end, start, end) => {
+            error!(f, "Global {:?} is declared more than once", name)?;
+            write_locations(f, &start, &end, contents)?;
+            writeln!(f, "First declared:")?;
+            write_locations(f, &first_start, &first_end, contents)?;
+        }
+        StaticAnalysisError::AssignToConst(ref name, start, end) => {
+            error!(f, "Assignment to const {:?}", name)?;
+            write_locations(f, &start, &end, contents)?;
         }
     }
     Ok(())
 }
 
+pub fn write_warnings(
+    f: &mut dyn Write,
+    warnings: &[LintWarning],
+    contents: &FileData,
+) -> OuterResult<()> {
+    for warning in warnings {
+        write_lint_warning(f, warning, LintLevel::Warn, contents)
+            .and_then(|_| writeln!(f))
+            .map_err(|_| OuterError::OutputError)?;
+    }
+    Ok(())
+}
+
+/// Denied lints are written as errors
+fn write_lint_warning(
+    f: &mut dyn Write,
+    warning: &LintWarning,
+    level: LintLevel,
+    contents: &FileData,
+) -> io::Result<()> {
+    let (message, start, end) = match *warning {
+        LintWarning::UnusedLocal(ref name, start, end) => {
+            (format!("Variable {:?} is never used", name), start, end)
+        }
+        LintWarning::UnusedParameter(ref name, ref fname, start, end) => (
+            format!("Parameter {:?} of function {:?} is never used", name, fname),
+            start,
+            end,
+        ),
+        LintWarning::UnreachableFunction(ref fname, start, end) => (
+            format!("Function {:?} can never be called", fname),
+            start,
+            end,
+        ),
+        LintWarning::UnreachableCode(start, end) => {
+            ("Code after a return can never run".to_string(), start, end)
+        }
+        LintWarning::InfiniteLoop(start, end) => (
+            "Loop condition is always true and nothing in it returns or breaks".to_string(),
+            start,
+            end,
+        ),
+        LintWarning::NoEffect(start, end) => (
+            "Expression has no effect as its result is discarded".to_string(),
+            start,
+            end,
+        ),
+    };
+    let level_name = match level {
+        LintLevel::Deny => {
+            error!(f, "{}", message)?;
+            "deny"
+        }
+        LintLevel::Warn | LintLevel::Allow => {
+            warning!(f, "{}", message)?;
+            "warn"
+        }
+    };
+    write_locations(f, &start, &end, contents)?;
+    writeln!(
+        f,
+        "note: lint {:?} is set to {}",
+        warning.lint().name(),
+        level_name
+    )
+}
+
+fn write_suggestion(f: &mut dyn Write, suggestion: &Option<String>) -> io::Result<()> {
+    match *suggestion {
+        Some(ref name) => writeln!(f, "note: did you mean {:?}?", name),
+        None => Ok(()),
+    }
+}
+
 fn write_location(f: &mut dyn Write, location: &Location, contents: &FileData) -> io::Result<()> {
     write_location_at(f, location, contents)?;
     if let Some(file) = location.file {
@@ -145,7 +390,11 @@ fn write_location(f: &mut dyn Write, location: &Location, contents: &FileData) -
         let line_start = find_line_start(location, file_content);
         write_previous_line(f, location, line_start, file_content)?;
         let line_end = find_line_end(location, file_content);
-        write_single_line_error(f, location, line_start, line_end, 1, file_content)?;
+        let len = file_content[location.file_offset_bytes..]
+            .chars()
+            .next()
+            .map_or(0, char::len_utf8);
+        write_single_line_error(f, location, line_start, line_end, len, file_content)?;
         write_next_line(f, location, line_end, file_content)?;
     }
     Ok(())
@@ -158,6 +407,32 @@ fn write_locations(
     contents: &FileData,
 ) -> io::Result<()> {
     write_location_at(f, start, contents)?;
+    write_code(f, start, end, contents)
+}
+
+fn write_stack_frame(f: &mut dyn Write, frame: &StackFrame, contents: &FileData) -> io::Result<()> {
+    let start = &frame.site.start;
+    writeln!(
+        f,
+        "in {} at {}:{}",
+        frame
+            .function
+            .as_ref()
+            .map_or("global initialiser", String::as_str),
+        file_name(start, contents),
+        start.line
+    )?;
+    write_code(f, start, &frame.site.end, contents)
+}
+
+/// The lines from start to end with the code between them highlighted, along with a line either
+/// side for context
+fn write_code(
+    f: &mut dyn Write,
+    start: &Location,
+    end: &Location,
+    contents: &FileData,
+) -> io::Result<()> {
     if let Some(file) = start.file {
         let file_content = contents.get_contents(file);
         let line_start = find_line_start(start, file_content);
@@ -207,15 +482,14 @@ fn write_locations(
 }
 
 fn write_location_at(f: &mut dyn Write, location: &Location, files: &FileData) -> io::Result<()> {
-    writeln!(
-        f,
-        "At: {}:{}",
-        location
-            .file
-            .map(|f| files.get_name(f))
-            .unwrap_or("unknown"),
-        location.line
-    )
+    writeln!(f, "At: {}:{}", file_name(location, files), location.line)
+}
+
+fn file_name<'a>(location: &Location, files: &'a FileData) -> &'a str {
+    location
+        .file
+        .map(|f| files.get_name(f))
+        .unwrap_or("unknown")
 }
 
 fn find_line_start(location: &Location, file_content: &str) -> usize {
@@ -277,8 +551,8 @@ fn write_single_line_error(
 
 #[cfg(test)]
 mod tests {
-    use super::super::grammar::parse_Programme;
-    use super::super::lexer::{Location, Matcher};
+    use super::super::lexer::Location;
+    use super::super::parse_programme;
     use super::*;
 
     macro_rules! test_parse_error {
@@ -289,21 +563,20 @@ mod tests {
                 let file_name = "test.sl";
                 let mut contents = FileData::new();
                 let file = contents.add(file_name.to_string(), text.to_string());
-                let matcher = Matcher::new(file, text);
-                let parse_err = parse_Programme(matcher).unwrap_err();
+                let (_, parse_errs) = parse_programme(file, text);
                 let mut output = Vec::new();
-                write_parse_error(&mut output, parse_err, &contents).unwrap();
+                write_parse_error(&mut output, parse_errs, &contents).unwrap();
                 assert_eq!(String::from_utf8(output).unwrap(), $expected)
             }
         };
     }
 
-    test_parse_error! {eof_in_string_literal, "\
+    test_parse_error! {eol_in_string_literal, "\
 function main() {
     let a = \"sdsds
 }
 ", format!("\
-{}: Found end of file whilst looking for end of string literal
+{}: Found end of line whilst looking for end of string literal, use '\\n' for a line break
 At: test.sl:2
 1 |function main() {{
 2 |    let a = {}sdsds
@@ -315,7 +588,7 @@ function main() {\r
     let a = \"sdsds\r
 }\r
 ", format!("\
-{}: Found end of file whilst looking for end of string literal
+{}: Found end of line whilst looking for end of string literal, use '\\n' for a line break
 At: test.sl:2
 1 |function main() {{
 2 |    let a = {}sdsds
@@ -325,11 +598,109 @@ At: test.sl:2
     test_parse_error! {no_surrounding_lines, "\
 function main() { let a = \"sdsds }
 ", format!("\
-{}: Found end of file whilst looking for end of string literal
+{}: Found end of line whilst looking for end of string literal, use '\\n' for a line break
 At: test.sl:1
 1 |function main() {{ let a = {}sdsds }}
 ", Red.paint("error"), Red.bold().paint("\""))}
 
+    test_parse_error! {unknown_escape_in_string_literal, "\
+function main() {
+    let a = \"tab\\tnewline\\m\";
+}
+", format!("\
+{}: Unknown escape sequence '\\m'
+At: test.sl:2
+1 |function main() {{
+2 |    let a = \"tab\\tnewline{}m\";
+3 |}}
+", Red.paint("error"), Red.bold().paint("\\"))}
+
+    test_parse_error! {integer_literal_out_of_range, "\
+function main() {
+    return 99999999999;
+}
+", format!("\
+{}: Integer literal out of range, decimal literals must be from -2147483648 to 2147483647 \
+and hex, octal or binary literals must fit in 32 bits
+At: test.sl:2
+1 |function main() {{
+2 |    return {}9999999999;
+3 |}}
+", Red.paint("error"), Red.bold().paint("9"))}
+
+    test_parse_error! {illegal_unicode_char, "\
+function main() {
+    let café = 1 € 2;
+}
+", format!("\
+{}: Illegal character '€' found
+At: test.sl:2
+1 |function main() {{
+2 |    let café = 1 {} 2;
+3 |}}
+", Red.paint("error"), Red.bold().paint("€"))}
+
+    test_parse_error! {reports_every_lexical_error, "\
+function main() {
+    let a = 1 $ 2;
+    let b = 'xy';
+    return \"abc;
+}
+", format!("\
+{}: Illegal character '$' found
+At: test.sl:2
+1 |function main() {{
+2 |    let a = 1 {} 2;
+3 |    let b = 'xy';
+
+{}: Unterminated or oversized character literal
+At: test.sl:3
+2 |    let a = 1 $ 2;
+3 |    let b = {}xy';
+4 |    return \"abc;
+
+{}: Found end of line whilst looking for end of string literal, use '\\n' for a line break
+At: test.sl:4
+3 |    let b = 'xy';
+4 |    return {}abc;
+5 |}}
+", Red.paint("error"), Red.bold().paint("$"),
+    Red.paint("error"), Red.bold().paint("'"),
+    Red.paint("error"), Red.bold().paint("\""))}
+
+    #[test]
+    fn import_not_found() {
+        let mut contents = FileData::new();
+        let file = contents.add(
+            "test.sl".to_string(),
+            "import \"util.sl\";\nfunction main() {}\n".to_string(),
+        );
+        let err = ImportError::NotFound(
+            "util.sl".to_string(),
+            vec!["util.sl".to_string(), "lib/util.sl".to_string()],
+            Location::new(file, 1, 0, 0),
+            Location::new(file, 1, 16, 16),
+        );
+        let mut output = Vec::new();
+        write_import_errors(&mut output, &[err], &contents).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                "\
+{}: Couldn't find import \"util.sl\"
+At: test.sl:1
+1 |{};
+2 |function main() {{}}
+Looked for:
+    util.sl
+    lib/util.sl
+",
+                Red.paint("error"),
+                Red.bold().paint("import \"util.sl\"")
+            )
+        );
+    }
+
     test_parse_error! {missing_curly, "\
 function main()
     return 1 + 3;
@@ -343,6 +714,29 @@ At: test.sl:2
 Expected one of \"{{\"
 ", Red.paint("error"), Red.bold().paint("return"))}
 
+    test_parse_error! {reports_every_syntax_error, "\
+function main() {
+    let 1 = 2;
+    let = 3;
+    return 0;
+}
+", format!("\
+{}: Unrecognized token Integer(1)
+At: test.sl:2
+1 |function main() {{
+2 |    let {} = 2;
+3 |    let = 3;
+Expected one of IDENTIFIER
+
+{}: Unrecognized token Equal
+At: test.sl:3
+2 |    let 1 = 2;
+3 |    let {} 3;
+4 |    return 0;
+Expected one of IDENTIFIER
+", Red.paint("error"), Red.bold().paint("1"),
+    Red.paint("error"), Red.bold().paint("="))}
+
     macro_rules! test_write_lines {
         ($test_name:ident, $content:expr,
             ($start_line:expr, $start_line_offset:expr, $start_offset:expr),
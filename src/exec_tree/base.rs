@@ -1,5 +1,6 @@
 pub use super::super::ast;
-use exec_tree::error::ExecResult;
+use exec_tree::error::{ExecError, ExecResult, RuntimeFailureKind, StaticAnalysisError};
+use file_data::FileHandle;
 use lexer::Location;
 use std::cell::RefCell;
 use std::collections::HashMap;
@@ -15,6 +16,39 @@ pub enum Value {
     Array(Array),
 }
 
+/// Arrays longer than this are only shown by their length in stack traces
+const MAX_SHOWN_ITEMS: usize = 8;
+
+impl Value {
+    /// A short form for stack traces. Small arrays are shown in full, but the arrays nested in them
+    /// are only shown by their length.
+    pub fn summary(&self) -> String {
+        self.summarise(true)
+    }
+
+    fn summarise(&self, expand: bool) -> String {
+        match *self {
+            Value::Integer(n) => n.to_string(),
+            Value::Array(ref array) => {
+                let array = array.borrow();
+                if expand && array.len() <= MAX_SHOWN_ITEMS {
+                    let items = array
+                        .iter()
+                        .map(|item| item.summarise(false))
+                        .collect::<Vec<_>>();
+                    format!("[{}]", items.join(", "))
+                } else {
+                    format!(
+                        "[..{} item{}]",
+                        array.len(),
+                        if array.len() == 1 { "" } else { "s" }
+                    )
+                }
+            }
+        }
+    }
+}
+
 impl From<Vec<Value>> for Value {
     fn from(value: Vec<Value>) -> Value {
         Value::Array(Rc::new(RefCell::new(value.into_boxed_slice())))
@@ -26,75 +60,126 @@ pub trait Callable {
 }
 
 struct StarLangFunction {
+    name: String,
+    parameters: Vec<String>,
     stmts: Vec<Box<dyn Statement>>,
     max_locals: usize,
 }
 
+type PlatformFunctionBox = Box<dyn Fn(&Globals, Vec<Value>) -> ExecResult<Value>>;
+
 struct PlatformFunction {
-    func: Box<dyn Fn(&Globals, Vec<Value>) -> Value>,
+    func: PlatformFunctionBox,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub struct FunctionId {
     idx: usize,
 }
 
 struct FunctionDeclaration {
     id: FunctionId,
+    public: bool,
+    parameters: Vec<String>,
+    /// Where the function's signature is, platform functions have none
+    site: Option<CodeSite>,
+}
+
+/// Why a call couldn't be resolved to a function
+pub enum FunctionLookupError {
+    UnknownModule,
+    UnknownFunction,
+    Private,
+}
+
+struct ModuleDeclaration {
+    name: String,
+    imports: Vec<FileHandle>,
+}
+
+/// Functions and globals are declared in a module, except for platform functions which have none
+type ItemKey = (Option<FileHandle>, String);
+
+/// Ordered by declaration, which is the order globals are initialised in
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GlobalId {
+    idx: usize,
+}
+
+struct GlobalDeclaration {
+    id: GlobalId,
+    mutable: bool,
+    site: CodeSite,
 }
 
 pub struct Globals<'a> {
-    function_declarations: HashMap<String, FunctionDeclaration>,
+    function_declarations: HashMap<ItemKey, FunctionDeclaration>,
     functions: Vec<Box<dyn Callable>>,
+    module_declarations: HashMap<FileHandle, ModuleDeclaration>,
+    prelude: Option<FileHandle>,
+    /// The module whose items are being declared or built
+    current_module: Option<FileHandle>,
+    global_declarations: HashMap<ItemKey, GlobalDeclaration>,
+    global_values: RefCell<Vec<Value>>,
+    /// Globals with an index below this have had their initialiser built, so can be read
+    initialised_globals: usize,
+    /// Whether arithmetic reports overflow as a failure rather than wrapping
+    checked_arithmetic: bool,
     input: RefCell<io::Bytes<&'a mut dyn io::Read>>,
     output: RefCell<&'a mut dyn io::Write>,
 }
 
-fn starlang_new(_globals: &Globals, args: Vec<Value>) -> Value {
-    match args[0] {
-        Value::Integer(n) => Value::from(vec![Value::Integer(0); n as usize]),
-        _ => {
-            panic!("platform function 'new' expected int but recieved array");
-        }
+/// Platform functions don't know where they were called from, the call adds its site as the
+/// failure is returned through it
+fn platform_failure(kind: RuntimeFailureKind) -> ExecError {
+    ExecError::RuntimeFailure(kind, Vec::new())
+}
+
+fn int_arg(function: &'static str, parameter: &'static str, arg: &Value) -> ExecResult<i32> {
+    match *arg {
+        Value::Integer(n) => Ok(n),
+        Value::Array(_) => Err(platform_failure(RuntimeFailureKind::ArgumentExpectedInt {
+            function,
+            parameter,
+        })),
     }
 }
 
-fn starlang_getc(globals: &Globals, _args: Vec<Value>) -> Value {
-    Value::Integer(
-        globals
-            .input
-            .borrow_mut()
-            .next()
-            .map(|result| result.unwrap() as i32)
-            .unwrap_or(-1),
-    )
+fn starlang_new(_globals: &Globals, args: Vec<Value>) -> ExecResult<Value> {
+    match int_arg("new", "size", &args[0])? {
+        n if n < 0 => Err(platform_failure(RuntimeFailureKind::NegativeArraySize(n))),
+        n => Ok(Value::from(vec![Value::Integer(0); n as usize])),
+    }
 }
 
-fn starlang_putc(globals: &Globals, args: Vec<Value>) -> Value {
-    match args[0] {
-        Value::Integer(c) => {
-            let output = [c as u8];
-            globals.output.borrow_mut().write_all(&output).unwrap();
-            Value::Integer(0)
-        }
-        _ => {
-            panic!("platform function 'new' expected int but recieved array");
-        }
+/// Gives -1 at the end of the input
+fn starlang_getc(globals: &Globals, _args: Vec<Value>) -> ExecResult<Value> {
+    match globals.input.borrow_mut().next() {
+        Some(Ok(byte)) => Ok(Value::Integer(i32::from(byte))),
+        Some(Err(err)) => Err(platform_failure(RuntimeFailureKind::ReadFailed(err.kind()))),
+        None => Ok(Value::Integer(-1)),
     }
 }
 
-fn starlang_len(_globals: &Globals, args: Vec<Value>) -> Value {
-    Value::Integer(match args[0] {
+fn starlang_putc(globals: &Globals, args: Vec<Value>) -> ExecResult<Value> {
+    let output = [int_arg("putc", "char", &args[0])? as u8];
+    globals
+        .output
+        .borrow_mut()
+        .write_all(&output)
+        .map_err(|err| platform_failure(RuntimeFailureKind::WriteFailed(err.kind())))?;
+    Ok(Value::Integer(0))
+}
+
+fn starlang_len(_globals: &Globals, args: Vec<Value>) -> ExecResult<Value> {
+    Ok(Value::Integer(match args[0] {
         Value::Integer(_) => -1,
         Value::Array(ref array) => array.borrow().len() as i32,
-    })
+    }))
 }
 
-fn starlang_exit(_globals: &Globals, args: Vec<Value>) -> Value {
-    match args[0] {
-        Value::Integer(n) => exit(n),
-        Value::Array(_) => panic!("exit called with array"),
-    }
+fn starlang_exit(_globals: &Globals, args: Vec<Value>) -> ExecResult<Value> {
+    exit(int_arg("exit", "status", &args[0])?)
 }
 
 impl<'b> Globals<'b> {
@@ -102,44 +187,255 @@ impl<'b> Globals<'b> {
         let mut rv = Globals {
             function_declarations: HashMap::new(),
             functions: Vec::new(),
+            module_declarations: HashMap::new(),
+            prelude: None,
+            current_module: None,
+            global_declarations: HashMap::new(),
+            global_values: RefCell::new(Vec::new()),
+            initialised_globals: 0,
+            checked_arithmetic: false,
             input: RefCell::new(input.bytes()),
             output: RefCell::new(output),
         };
-        rv.define_platform_func("new", Box::new(starlang_new));
-        rv.define_platform_func("len", Box::new(starlang_len));
-        rv.define_platform_func("getc", Box::new(starlang_getc));
-        rv.define_platform_func("putc", Box::new(starlang_putc));
-        rv.define_platform_func("exit", Box::new(starlang_exit));
+        rv.define_platform_func("new", &["size"], Box::new(starlang_new));
+        rv.define_platform_func("len", &["array"], Box::new(starlang_len));
+        rv.define_platform_func("getc", &[], Box::new(starlang_getc));
+        rv.define_platform_func("putc", &["char"], Box::new(starlang_putc));
+        rv.define_platform_func("exit", &["status"], Box::new(starlang_exit));
         rv
     }
-    pub fn declare_func(&mut self, func: &ast::Function) {
+    pub fn declare_module(&mut self, module: &ast::Module) {
+        if module.prelude {
+            self.prelude = Some(module.file);
+        }
+        self.module_declarations.insert(
+            module.file,
+            ModuleDeclaration {
+                name: module.name.clone(),
+                imports: module.imports.clone(),
+            },
+        );
+    }
+    /// Only affects the arithmetic built after it's set
+    pub fn set_checked_arithmetic(&mut self, checked: bool) {
+        self.checked_arithmetic = checked;
+    }
+    pub fn checked_arithmetic(&self) -> bool {
+        self.checked_arithmetic
+    }
+    /// Declarations and references are made in the current module until the next call
+    pub fn enter_module(&mut self, module: FileHandle) {
+        self.current_module = Some(module);
+    }
+    fn key(&self, name: &str) -> ItemKey {
+        (self.current_module, name.to_string())
+    }
+    /// Only the first definition of a name in a module is declared, later ones are errors
+    // Duplicates are rare and reported once, so the size of the error doesn't matter
+    #[allow(clippy::result_large_err)]
+    pub fn declare_func(&mut self, func: &ast::Function) -> Result<(), StaticAnalysisError> {
+        let key = self.key(&func.name);
+        if let Some(&FunctionDeclaration {
+            site: Some(first), ..
+        }) = self.function_declarations.get(&key)
+        {
+            return Err(StaticAnalysisError::DuplicateFunction(
+                func.name.clone(),
+                first.start,
+                first.end,
+                func.start,
+                func.end,
+            ));
+        }
         let id = self.next_func_id();
-        self.function_declarations
-            .insert(func.name.clone(), FunctionDeclaration { id });
+        self.function_declarations.insert(
+            key,
+            FunctionDeclaration {
+                id,
+                public: func.public,
+                parameters: func.arguments.iter().map(|arg| arg.to_string()).collect(),
+                site: Some(CodeSite {
+                    start: func.start,
+                    end: func.end,
+                }),
+            },
+        );
+        Ok(())
+    }
+    /// Only the first declaration of a name in a module is kept, like functions
+    #[allow(clippy::result_large_err)]
+    pub fn declare_global(&mut self, global: &ast::Global) -> Result<(), StaticAnalysisError> {
+        let key = self.key(global.name);
+        if let Some(first) = self.global_declarations.get(&key) {
+            return Err(StaticAnalysisError::DuplicateGlobal(
+                global.name.to_string(),
+                first.site.start,
+                first.site.end,
+                global.start,
+                global.end,
+            ));
+        }
+        let id = GlobalId {
+            idx: self.global_values.borrow().len(),
+        };
+        self.global_declarations.insert(
+            key,
+            GlobalDeclaration {
+                id,
+                mutable: global.mutable,
+                site: CodeSite {
+                    start: global.start,
+                    end: global.end,
+                },
+            },
+        );
+        self.global_values.borrow_mut().push(Value::Integer(0));
+        Ok(())
+    }
+    pub fn reference_global(&self, name: &str) -> Option<GlobalId> {
+        self.global_declarations.get(&self.key(name)).map(|v| v.id)
+    }
+    /// The id of the global if this is the declaration that was kept, rather than a duplicate
+    pub fn declared_global(&self, global: &ast::Global) -> Option<GlobalId> {
+        self.global_declarations
+            .get(&self.key(global.name))
+            .filter(|decl| decl.site.start == global.start)
+            .map(|decl| decl.id)
+    }
+    pub fn is_global_mutable(&self, name: &str) -> bool {
+        self.global_declarations
+            .get(&self.key(name))
+            .is_some_and(|v| v.mutable)
+    }
+    pub fn is_global_initialised(&self, global_id: GlobalId) -> bool {
+        global_id.idx < self.initialised_globals
+    }
+    /// Called once the initialiser for the global has been built, globals are initialised in
+    /// declaration order
+    pub fn mark_global_initialised(&mut self, global_id: GlobalId) {
+        self.initialised_globals = global_id.idx + 1;
     }
-    pub fn has_main(&self) -> bool {
-        self.function_declarations.contains_key("main")
+    pub fn get_global(&self, global_id: GlobalId) -> Value {
+        self.global_values.borrow()[global_id.idx].clone()
     }
-    pub fn get_main(&self) -> &dyn Callable {
-        self.lookup_func(self.reference_func("main").expect("No main defined"))
+    pub fn set_global(&self, global_id: GlobalId, value: Value) {
+        self.global_values.borrow_mut()[global_id.idx] = value;
     }
-    pub fn define_func(&mut self, name: &str, stmts: Vec<Box<dyn Statement>>, max_locals: usize) {
-        match self.function_declarations.get(name) {
+    /// The entry point must be defined in the module itself but needn't be public. Gives the
+    /// function along with its parameters and signature.
+    pub fn get_entry_point(
+        &self,
+        module: FileHandle,
+        name: &str,
+    ) -> Option<(FunctionId, &[String], CodeSite)> {
+        self.function_declarations
+            .get(&(Some(module), name.to_string()))
+            .and_then(|decl| decl.site.map(|site| (decl.id, &decl.parameters[..], site)))
+    }
+    /// Functions that failed to be declared as duplicates are dropped
+    pub fn define_func(
+        &mut self,
+        func: &ast::Function,
+        stmts: Vec<Box<dyn Statement>>,
+        max_locals: usize,
+    ) {
+        match self.function_declarations.get(&self.key(&func.name)) {
             Some(decl) => {
+                if decl.site.is_some_and(|site| site.start != func.start) {
+                    return;
+                }
                 if self.functions.len() != decl.id.idx {
                     panic!(
                         "Attempting to define function {} out of declaration order.",
-                        name
+                        func.name
                     )
                 }
-                self.functions
-                    .push(Box::new(StarLangFunction { stmts, max_locals }))
+                self.functions.push(Box::new(StarLangFunction {
+                    name: func.name.clone(),
+                    parameters: decl.parameters.clone(),
+                    stmts,
+                    max_locals,
+                }))
             }
-            None => unreachable!("Attempting to define undeclared function {}", name),
+            None => unreachable!("Attempting to define undeclared function {}", func.name),
         }
     }
-    pub fn reference_func(&self, name: &str) -> Option<FunctionId> {
-        self.function_declarations.get(name).map(|v| v.id)
+    /// Unqualified names are looked for in the current module, then the platform functions and
+    /// then the public functions of the prelude. Qualified names must name the current module, one
+    /// it imports or the prelude. Gives the function along with its parameters.
+    pub fn reference_func(
+        &self,
+        module: Option<&str>,
+        name: &str,
+    ) -> Result<(FunctionId, &[String]), FunctionLookupError> {
+        let candidates = match module {
+            Some(module) => vec![Some(
+                self.resolve_module(module)
+                    .ok_or(FunctionLookupError::UnknownModule)?,
+            )],
+            None => vec![self.current_module, None, self.prelude],
+        };
+        for candidate in candidates {
+            if let Some(decl) = self
+                .function_declarations
+                .get(&(candidate, name.to_string()))
+            {
+                if decl.public || candidate.is_none() || candidate == self.current_module {
+                    return Ok((decl.id, &decl.parameters));
+                } else {
+                    return Err(FunctionLookupError::Private);
+                }
+            }
+        }
+        Err(FunctionLookupError::UnknownFunction)
+    }
+    /// A platform function, ignoring any function of the same name that a module defines
+    pub fn platform_func(&self, name: &str) -> Option<FunctionId> {
+        self.function_declarations
+            .get(&(None, name.to_string()))
+            .map(|decl| decl.id)
+    }
+    fn resolve_module(&self, name: &str) -> Option<FileHandle> {
+        let imports = self
+            .current_module
+            .and_then(|module| self.module_declarations.get(&module))
+            .map_or(&[][..], |decl| &decl.imports[..]);
+        self.current_module
+            .iter()
+            .chain(imports)
+            .chain(self.prelude.iter())
+            .cloned()
+            .find(|module| {
+                self.module_declarations
+                    .get(module)
+                    .is_some_and(|decl| decl.name == name)
+            })
+    }
+    /// The names `reference_func` would find for the module, used to suggest alternatives
+    pub fn callable_names(&self, module: Option<&str>) -> Vec<&str> {
+        let modules = match module {
+            Some(module) => match self.resolve_module(module) {
+                Some(module) => vec![Some(module)],
+                None => return Vec::new(),
+            },
+            None => vec![self.current_module, None, self.prelude],
+        };
+        self.function_declarations
+            .iter()
+            .filter(|&(&(decl_module, _), decl)| {
+                modules.contains(&decl_module)
+                    && (decl.public || decl_module == self.current_module)
+            })
+            .map(|((_, name), _)| name.as_str())
+            .collect()
+    }
+    /// The globals of the current module
+    pub fn global_names(&self) -> Vec<&str> {
+        self.global_declarations
+            .keys()
+            .filter(|&&(module, _)| module == self.current_module)
+            .map(|(_, name)| name.as_str())
+            .collect()
     }
     pub fn lookup_func(&self, func_id: FunctionId) -> &dyn Callable {
         &*self.functions[func_id.idx]
@@ -149,14 +445,17 @@ impl<'b> Globals<'b> {
             idx: self.function_declarations.len(),
         }
     }
-    fn define_platform_func(
-        &mut self,
-        name: &str,
-        func: Box<dyn Fn(&Globals, Vec<Value>) -> Value>,
-    ) {
+    fn define_platform_func(&mut self, name: &str, parameters: &[&str], func: PlatformFunctionBox) {
         let id = self.next_func_id();
-        self.function_declarations
-            .insert(name.to_string(), FunctionDeclaration { id });
+        self.function_declarations.insert(
+            (None, name.to_string()),
+            FunctionDeclaration {
+                id,
+                public: true,
+                parameters: parameters.iter().map(|param| param.to_string()).collect(),
+                site: None,
+            },
+        );
         if self.functions.len() != id.idx {
             panic!(
                 "Attempting to define function {} out of declaration order.",
@@ -174,6 +473,8 @@ pub struct Locals {
 pub enum FunctionState {
     Return(Value),
     NoReturn,
+    Break,
+    Continue,
 }
 
 pub trait Statement {
@@ -211,10 +512,23 @@ impl ExprBox {
 
 pub trait LExpr {
     fn assign(&self, globals: &Globals, locals: &mut Locals, value: Value) -> ExecResult<()>;
+    /// Replace the value with `func` of the current value, only evaluating the target once
+    fn modify(
+        &self,
+        globals: &Globals,
+        locals: &mut Locals,
+        func: &dyn Fn(Value) -> ExecResult<Value>,
+    ) -> ExecResult<()>;
+}
+
+struct Scope {
+    vars: HashMap<String, usize>,
+    /// The first local slot used by the scope, slots from here on are free once it's popped
+    first_local: usize,
 }
 
 pub struct ScopeStack {
-    scopes: Vec<HashMap<String, usize>>,
+    scopes: Vec<Scope>,
     current_locals: usize,
     max_locals: usize,
 }
@@ -222,29 +536,58 @@ pub struct ScopeStack {
 impl ScopeStack {
     pub fn new() -> ScopeStack {
         ScopeStack {
-            scopes: vec![HashMap::new()],
+            scopes: vec![Scope {
+                vars: HashMap::new(),
+                first_local: 0,
+            }],
             current_locals: 0,
             max_locals: 0,
         }
     }
 
+    pub fn push_scope(&mut self) {
+        self.scopes.push(Scope {
+            vars: HashMap::new(),
+            first_local: self.current_locals,
+        });
+    }
+
+    /// Variables declared since the matching `push_scope` go out of scope and their slots are
+    /// reused by later declarations
+    pub fn pop_scope(&mut self) {
+        let scope = self
+            .scopes
+            .pop()
+            .expect("Popped more scopes than were pushed");
+        self.current_locals = scope.first_local;
+    }
+
     pub fn declare(&mut self, name: &str) -> usize {
         let rv = self.current_locals;
         self.current_locals += 1;
         if self.current_locals > self.max_locals {
             self.max_locals = self.current_locals;
         }
-        self.scopes.last_mut().unwrap().insert(name.to_string(), rv);
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .vars
+            .insert(name.to_string(), rv);
         rv
     }
 
-    pub fn get(&self, name: &str) -> usize {
-        for scope in self.scopes.iter().rev() {
-            if let Some(idx) = scope.get(name) {
-                return *idx;
-            }
-        }
-        panic!("Attempt to access varaible '{}' when not in scope", name)
+    pub fn lookup(&self, name: &str) -> Option<usize> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.vars.get(name).cloned())
+    }
+
+    /// Every variable that's in scope
+    pub fn names(&self) -> impl Iterator<Item = &str> + '_ {
+        self.scopes
+            .iter()
+            .flat_map(|scope| scope.vars.keys().map(String::as_str))
     }
 
     pub fn get_max_locals(&self) -> usize {
@@ -254,18 +597,64 @@ impl ScopeStack {
 
 impl Callable for StarLangFunction {
     fn call(&self, globals: &Globals, args: Vec<Value>) -> ExecResult<Value> {
+        // Cheap as arrays are shared, and only described if the call fails
+        let call_args = args.clone();
         let mut locals = Locals { vars: args };
         locals.vars.reserve(self.max_locals);
         while locals.vars.len() < self.max_locals {
             locals.vars.push(Value::Integer(0));
         }
-        exec_block(globals, &mut locals, &self.stmts).map(|r| match r {
-            FunctionState::Return(val) => val,
-            FunctionState::NoReturn => Value::Integer(0),
-        })
+        exec_block(globals, &mut locals, &self.stmts)
+            .map(|r| match r {
+                FunctionState::Return(val) => val,
+                FunctionState::NoReturn => Value::Integer(0),
+                FunctionState::Break | FunctionState::Continue => {
+                    unreachable!("Loop control outside of a loop should fail static analysis")
+                }
+            })
+            .map_err(|mut e| {
+                if let ExecError::RuntimeFailure(_, ref mut stack) = e {
+                    if let Some(frame) = stack.last_mut() {
+                        frame.function = Some(self.describe_call(&call_args));
+                    }
+                }
+                e
+            })
     }
 }
 
+impl StarLangFunction {
+    /// The parameters are shown with the values they were called with, even if the body has
+    /// reassigned them since
+    fn describe_call(&self, args: &[Value]) -> String {
+        let args = self
+            .parameters
+            .iter()
+            .zip(args)
+            .map(|(name, value)| format!("{}={}", name, value.summary()))
+            .collect::<Vec<_>>();
+        format!("{}({})", self.name, args.join(", "))
+    }
+}
+
+/// The functions of the module along with their ids, which are looked up in the current module
+pub fn module_functions<'a, 'b>(
+    globals: &Globals,
+    module: &'b ast::Module<'a>,
+) -> Vec<(FunctionId, &'b ast::Function<'a>)> {
+    module
+        .items
+        .iter()
+        .filter_map(|item| match *item {
+            ast::Item::Function(ref func) => globals
+                .reference_func(None, &func.name)
+                .ok()
+                .map(|(id, _)| (id, func)),
+            _ => None,
+        })
+        .collect()
+}
+
 pub fn exec_block(
     globals: &Globals,
     locals: &mut Locals,
@@ -273,8 +662,8 @@ pub fn exec_block(
 ) -> ExecResult<FunctionState> {
     for stmt in stmts {
         match stmt.do_stmt(globals, locals)? {
-            FunctionState::Return(val) => return Ok(FunctionState::Return(val)),
             FunctionState::NoReturn => {}
+            state => return Ok(state),
         }
     }
     Ok(FunctionState::NoReturn)
@@ -282,6 +671,6 @@ pub fn exec_block(
 
 impl Callable for PlatformFunction {
     fn call(&self, globals: &Globals, args: Vec<Value>) -> ExecResult<Value> {
-        Ok((self.func)(globals, args))
+        (self.func)(globals, args)
     }
 }
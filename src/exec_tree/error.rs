@@ -1,17 +1,65 @@
 use super::super::lexer::Location;
-use exec_tree::base::{CodeSite, ExprBox};
+use exec_tree::base::CodeSite;
+use exec_tree::lints::LintWarning;
+use file_data::FileHandle;
 use std;
 use std::fmt::{self, Display, Formatter};
+use std::io;
 
 #[derive(Debug, PartialEq)]
 pub enum RuntimeFailureKind {
     ExpectedIntGotArray,
+    ExpectedArrayGotInt,
+    DivisionByZero,
+    /// Only reported with checked arithmetic, otherwise the result wraps
+    Overflow,
+    /// A platform function was given an array where it needs an int
+    ArgumentExpectedInt {
+        function: &'static str,
+        parameter: &'static str,
+    },
+    NegativeArraySize(i32),
+    ReadFailed(io::ErrorKind),
+    WriteFailed(io::ErrorKind),
+    /// Negative indices are always out of bounds
+    IndexOutOfBounds {
+        index: i32,
+        len: usize,
+    },
+    /// The name of the entry point, which must return an int to use as the exit status
+    EntryPointReturnedArray(String),
 }
 
 impl Display for RuntimeFailureKind {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         match self {
             RuntimeFailureKind::ExpectedIntGotArray => write!(f, "Expected int got an array"),
+            RuntimeFailureKind::ExpectedArrayGotInt => write!(f, "Expected array got an int"),
+            RuntimeFailureKind::DivisionByZero => write!(f, "Division by zero"),
+            RuntimeFailureKind::Overflow => write!(f, "Integer overflow"),
+            RuntimeFailureKind::ArgumentExpectedInt {
+                function,
+                parameter,
+            } => write!(
+                f,
+                "Function {:?} expected an int for {:?} but was given an array",
+                function, parameter
+            ),
+            RuntimeFailureKind::ReadFailed(kind) => write!(f, "Failed to read input: {}", kind),
+            RuntimeFailureKind::WriteFailed(kind) => write!(f, "Failed to write output: {}", kind),
+            RuntimeFailureKind::NegativeArraySize(size) => {
+                write!(f, "Can't make an array with negative size {}", size)
+            }
+            RuntimeFailureKind::IndexOutOfBounds { index, len } => write!(
+                f,
+                "Index {} is out of bounds for an array of length {}",
+                index, len
+            ),
+            RuntimeFailureKind::EntryPointReturnedArray(ref name) => write!(
+                f,
+                "Expected {:?} to return an int for the exit status but it returned an array",
+                name
+            ),
         }
     }
 }
@@ -19,17 +67,64 @@ impl Display for RuntimeFailureKind {
 #[derive(Debug, PartialEq)]
 pub enum ExecError {
     StaticAnalysisFailed(Vec<StaticAnalysisError>),
-    RuntimeFailure(RuntimeFailureKind, Vec<CodeSite>),
+    RuntimeFailure(RuntimeFailureKind, Vec<StackFrame>),
 }
 pub type ExecResult<T> = std::result::Result<T, ExecError>;
 
-pub fn runtime_failure(kind: RuntimeFailureKind, expr: &ExprBox) -> ExecError {
-    ExecError::RuntimeFailure(kind, vec![expr.site])
+/// Where a function was when a runtime failure happened
+#[derive(Debug, PartialEq)]
+pub struct StackFrame {
+    pub site: CodeSite,
+    /// The function with its arguments, such as `f(a=1, b=[..3 items])`. It's filled in as the
+    /// failure leaves the function and stays empty for global initialisers.
+    pub function: Option<String>,
+}
+
+impl StackFrame {
+    pub fn new(site: CodeSite) -> Self {
+        StackFrame {
+            site,
+            function: None,
+        }
+    }
+}
+
+/// The stack starts with the frame of the failure, callers add their frames as it unwinds
+pub fn runtime_failure(kind: RuntimeFailureKind, site: CodeSite) -> ExecError {
+    ExecError::RuntimeFailure(kind, vec![StackFrame::new(site)])
 }
 
 #[derive(Debug, PartialEq)]
 pub enum StaticAnalysisError {
-    CallUnknownFunction(String, Location, Location),
+    /// The name and the closest name that could have been meant, if any are close
+    CallUnknownFunction(String, Option<String>, Location, Location),
+    /// The name, the parameters and how many arguments were given
+    WrongArgumentCount(String, Vec<String>, usize, Location, Location),
+    /// The name, then the first definition's signature and the duplicate's
+    DuplicateFunction(String, Location, Location, Location, Location),
+    CallPrivateFunction(String, Location, Location),
+    UnknownModule(String, Location, Location),
+    BreakOutsideLoop(Location, Location),
+    ContinueOutsideLoop(Location, Location),
+    /// Found by type inference, for operations that can only fail when run
+    ExpectedIntGotArray(Location, Location),
+    ExpectedArrayGotInt(Location, Location),
+    UndeclaredVariable(String, Option<String>, Location, Location),
+    AssignToUndeclaredVariable(String, Option<String>, Location, Location),
+    /// The entry point's name and the script it should be in
+    MissingEntryPoint(String, FileHandle),
+    /// The entry point's name and parameter count, it can only take the command line arguments
+    EntryPointParameters(String, usize, Location, Location),
+    /// Only variables and array elements can be assigned to
+    InvalidAssignmentTarget(Location, Location),
+    /// A lint that's set to deny found something
+    DeniedLint(LintWarning),
+    GlobalReadBeforeInitialised(String, Location, Location),
+    /// The function called by an initialiser and the global it can read, located at the call
+    CallReadsGlobalBeforeInitialised(String, String, Location, Location),
+    /// The name, then the first declaration's name and the duplicate's
+    DuplicateGlobal(String, Location, Location, Location, Location),
+    AssignToConst(String, Location, Location),
 }
 
 pub type StaticAnalysisErrors = Vec<StaticAnalysisError>;
@@ -1,5 +1,6 @@
 use super::base::*;
 use super::error::*;
+use super::suggest::closest_name;
 
 fn evaluate_expr_list(
     globals: &Globals,
@@ -12,27 +13,29 @@ fn evaluate_expr_list(
         .collect()
 }
 
-fn evaluate_to_int(globals: &Globals, locals: &Locals, expr: &ExprBox) -> ExecResult<i32> {
+pub fn evaluate_to_int(globals: &Globals, locals: &Locals, expr: &ExprBox) -> ExecResult<i32> {
     match expr.evaluate(globals, locals)? {
         Value::Integer(n) => Ok(n),
         Value::Array(_) => Err(runtime_failure(
             RuntimeFailureKind::ExpectedIntGotArray,
-            expr,
+            expr.site,
         )),
     }
 }
 
 pub fn evaluate_to_bool(globals: &Globals, locals: &Locals, expr: &ExprBox) -> ExecResult<bool> {
-    match expr.evaluate(globals, locals)? {
-        Value::Integer(n) => Ok(n != 0),
-        Value::Array(_) => unimplemented!(),
-    }
+    evaluate_to_int(globals, locals, expr).map(|n| n != 0)
 }
 
 macro_rules! evaluate_to_array {
     ($globals:expr, $locals:expr, $expr:expr, $ident:ident => $block:block) => {
-        match $expr.expr.evaluate($globals, $locals)? {
-            Value::Integer(_) => panic!("Required array got int"),
+        match $expr.evaluate($globals, $locals)? {
+            Value::Integer(_) => {
+                return Err(runtime_failure(
+                    RuntimeFailureKind::ExpectedArrayGotInt,
+                    $expr.site,
+                ))
+            }
             Value::Array(ref $ident) => $block,
         }
     };
@@ -46,6 +49,21 @@ impl Expr for BadExpr {
     }
 }
 
+impl LExpr for BadExpr {
+    fn assign(&self, _globals: &Globals, _locals: &mut Locals, _value: Value) -> ExecResult<()> {
+        unreachable!("Attempt to assign to bad expression")
+    }
+
+    fn modify(
+        &self,
+        _globals: &Globals,
+        _locals: &mut Locals,
+        _func: &dyn Fn(Value) -> ExecResult<Value>,
+    ) -> ExecResult<()> {
+        unreachable!("Attempt to assign to bad expression")
+    }
+}
+
 struct IntegerLiteral {
     value: i32,
 }
@@ -100,6 +118,17 @@ impl LExpr for Identifier {
         locals.vars[self.var_id] = value;
         Ok(())
     }
+
+    fn modify(
+        &self,
+        _globals: &Globals,
+        locals: &mut Locals,
+        func: &dyn Fn(Value) -> ExecResult<Value>,
+    ) -> ExecResult<()> {
+        let value = func(locals.vars[self.var_id].clone())?;
+        locals.vars[self.var_id] = value;
+        Ok(())
+    }
 }
 
 impl Expr for Identifier {
@@ -108,28 +137,63 @@ impl Expr for Identifier {
     }
 }
 
-struct BinaryIntegerOp<FnT: Fn(i32, i32) -> i32> {
+struct GlobalVariable {
+    global_id: GlobalId,
+}
+
+impl Expr for GlobalVariable {
+    fn evaluate(&self, globals: &Globals, _locals: &Locals) -> ExecResult<Value> {
+        Ok(globals.get_global(self.global_id))
+    }
+}
+
+impl LExpr for GlobalVariable {
+    fn assign(&self, globals: &Globals, _locals: &mut Locals, value: Value) -> ExecResult<()> {
+        globals.set_global(self.global_id, value);
+        Ok(())
+    }
+
+    fn modify(
+        &self,
+        globals: &Globals,
+        _locals: &mut Locals,
+        func: &dyn Fn(Value) -> ExecResult<Value>,
+    ) -> ExecResult<()> {
+        let value = func(globals.get_global(self.global_id))?;
+        globals.set_global(self.global_id, value);
+        Ok(())
+    }
+}
+
+/// The result of an integer operation, or why it failed
+pub type IntegerOpResult = Result<i32, RuntimeFailureKind>;
+
+struct BinaryIntegerOp<FnT: Fn(i32, i32) -> IntegerOpResult> {
     lhs_expr: ExprBox,
     rhs_expr: ExprBox,
     func: FnT,
+    /// Where to report the operation failing, such as on division by zero
+    site: CodeSite,
 }
 
-impl<FnT: Fn(i32, i32) -> i32 + 'static> BinaryIntegerOp<FnT> {
-    fn new(lhs_expr: ExprBox, rhs_expr: ExprBox, func: FnT) -> Self {
+impl<FnT: Fn(i32, i32) -> IntegerOpResult + 'static> BinaryIntegerOp<FnT> {
+    fn new(lhs_expr: ExprBox, rhs_expr: ExprBox, func: FnT, site: CodeSite) -> Self {
         BinaryIntegerOp {
             lhs_expr,
             rhs_expr,
             func,
+            site,
         }
     }
 }
 
-impl<FnT: Fn(i32, i32) -> i32> Expr for BinaryIntegerOp<FnT> {
+impl<FnT: Fn(i32, i32) -> IntegerOpResult> Expr for BinaryIntegerOp<FnT> {
     fn evaluate(&self, globals: &Globals, locals: &Locals) -> ExecResult<Value> {
-        Ok(Value::Integer((self.func)(
-            evaluate_to_int(globals, locals, &self.lhs_expr)?,
-            evaluate_to_int(globals, locals, &self.rhs_expr)?,
-        )))
+        let lhs = evaluate_to_int(globals, locals, &self.lhs_expr)?;
+        let rhs = evaluate_to_int(globals, locals, &self.rhs_expr)?;
+        (self.func)(lhs, rhs)
+            .map(Value::Integer)
+            .map_err(|kind| runtime_failure(kind, self.site))
     }
 }
 
@@ -170,24 +234,45 @@ impl Expr for Call {
         )
     }
 
+    /// Failures in the arguments happen in the caller's frame, so only those from the call itself
+    /// get a frame for the call site
     fn evaluate_ex(
         &self,
         globals: &Globals,
         locals: &Locals,
         site: &CodeSite,
     ) -> ExecResult<Value> {
-        self.evaluate(globals, locals).map_err(|mut e| {
-            if let ExecError::RuntimeFailure(_, stack) = &mut e {
-                stack.push(*site)
-            }
-            e
-        })
+        let args = evaluate_expr_list(globals, locals, &self.argument_exprs)?;
+        globals
+            .lookup_func(self.func)
+            .call(globals, args)
+            .map_err(|mut e| {
+                if let ExecError::RuntimeFailure(_, stack) = &mut e {
+                    stack.push(StackFrame::new(*site))
+                }
+                e
+            })
     }
 }
 
 struct Subscription {
     array_expr: ExprBox,
     index_expr: ExprBox,
+    /// Kept for reporting bad indices, as an l-expr isn't boxed with its site
+    site: CodeSite,
+}
+
+impl Subscription {
+    fn check_index(&self, index: i32, len: usize) -> ExecResult<usize> {
+        if index >= 0 && (index as usize) < len {
+            Ok(index as usize)
+        } else {
+            Err(runtime_failure(
+                RuntimeFailureKind::IndexOutOfBounds { index, len },
+                self.site,
+            ))
+        }
+    }
 }
 
 impl Expr for Subscription {
@@ -195,7 +280,8 @@ impl Expr for Subscription {
         let index = evaluate_to_int(globals, locals, &self.index_expr)?;
         evaluate_to_array!(globals, locals, self.array_expr, array => {
             let array_borrow = array.borrow();
-            Ok(array_borrow[index as usize].clone())
+            let index = self.check_index(index, array_borrow.len())?;
+            Ok(array_borrow[index].clone())
         })
     }
 }
@@ -205,7 +291,24 @@ impl LExpr for Subscription {
         let index = evaluate_to_int(globals, locals, &self.index_expr)?;
         evaluate_to_array!(globals, locals, self.array_expr, array => {
             let mut array_borrow = array.borrow_mut();
-            array_borrow[index as usize] = value;
+            let index = self.check_index(index, array_borrow.len())?;
+            array_borrow[index] = value;
+        });
+        Ok(())
+    }
+
+    fn modify(
+        &self,
+        globals: &Globals,
+        locals: &mut Locals,
+        func: &dyn Fn(Value) -> ExecResult<Value>,
+    ) -> ExecResult<()> {
+        let index = evaluate_to_int(globals, locals, &self.index_expr)?;
+        evaluate_to_array!(globals, locals, self.array_expr, array => {
+            let mut array_borrow = array.borrow_mut();
+            let index = self.check_index(index, array_borrow.len())?;
+            let value = func(array_borrow[index].clone())?;
+            array_borrow[index] = value;
         });
         Ok(())
     }
@@ -227,16 +330,55 @@ impl Expr for BoolNot {
     }
 }
 
-struct UnaryIntegerOp<FnT: Fn(i32) -> i32> {
+struct UnaryIntegerOp<FnT: Fn(i32) -> IntegerOpResult> {
     expr: ExprBox,
     func: FnT,
+    /// Where to report the operation failing, such as on checked negation overflowing
+    site: CodeSite,
 }
 
-impl<FnT: Fn(i32) -> i32> Expr for UnaryIntegerOp<FnT> {
+impl<FnT: Fn(i32) -> IntegerOpResult> Expr for UnaryIntegerOp<FnT> {
     fn evaluate(&self, globals: &Globals, locals: &Locals) -> ExecResult<Value> {
-        Ok(Value::Integer((self.func)(evaluate_to_int(
-            globals, locals, &self.expr,
-        )?)))
+        (self.func)(evaluate_to_int(globals, locals, &self.expr)?)
+            .map(Value::Integer)
+            .map_err(|kind| runtime_failure(kind, self.site))
+    }
+}
+
+/// The function for a binary operator that takes and gives integers
+/// Arithmetic wraps and shift counts are masked to the low five bits, unless it's checked, in which
+/// case overflow and shifting by a count outside 0 to 31 fail. Division by zero always fails.
+pub fn integer_op(op: ast::BinaryOpCode, checked: bool) -> fn(i32, i32) -> IntegerOpResult {
+    use ast::BinaryOpCode::*;
+    fn overflow(result: Option<i32>) -> IntegerOpResult {
+        result.ok_or(RuntimeFailureKind::Overflow)
+    }
+    fn non_zero(divisor: i32) -> Result<i32, RuntimeFailureKind> {
+        match divisor {
+            0 => Err(RuntimeFailureKind::DivisionByZero),
+            _ => Ok(divisor),
+        }
+    }
+    match (op, checked) {
+        (Add, false) => |l, r| Ok(l.wrapping_add(r)),
+        (Sub, false) => |l, r| Ok(l.wrapping_sub(r)),
+        (Mul, false) => |l, r| Ok(l.wrapping_mul(r)),
+        (Div, false) => |l, r| Ok(l.wrapping_div(non_zero(r)?)),
+        (Mod, false) => |l, r| Ok(l.wrapping_rem(non_zero(r)?)),
+        (LeftShift, false) => |l, r| Ok(l.wrapping_shl(r as u32)),
+        (RightShift, false) => |l, r| Ok(l.wrapping_shr(r as u32)),
+        (Add, true) => |l, r| overflow(l.checked_add(r)),
+        (Sub, true) => |l, r| overflow(l.checked_sub(r)),
+        (Mul, true) => |l, r| overflow(l.checked_mul(r)),
+        (Div, true) => |l, r| overflow(l.checked_div(non_zero(r)?)),
+        (Mod, true) => |l, r| overflow(l.checked_rem(non_zero(r)?)),
+        // A negative count becomes too large a shift
+        (LeftShift, true) => |l, r| overflow(l.checked_shl(r as u32)),
+        (RightShift, true) => |l, r| overflow(l.checked_shr(r as u32)),
+        (BitOr, _) => |l, r| Ok(l | r),
+        (BitAnd, _) => |l, r| Ok(l & r),
+        (BitXor, _) => |l, r| Ok(l ^ r),
+        _ => unreachable!("{:?} doesn't have an integer form", op),
     }
 }
 
@@ -255,6 +397,10 @@ fn build_expr_list<'a>(
     (rv, failures)
 }
 
+fn closest_variable(globals: &Globals, scope_stack: &ScopeStack, name: &str) -> Option<String> {
+    closest_name(name, scope_stack.names().chain(globals.global_names()))
+}
+
 pub fn build_expr<'a>(
     globals: &Globals,
     scope_stack: &ScopeStack,
@@ -300,19 +446,38 @@ pub fn build_expr<'a>(
     match expr.kind {
         Number(n) => result!(IntegerLiteral { value: n }),
         Char(c) => result!(IntegerLiteral { value: c as i32 }),
-        String(s) => result!(StringLiteral { s: s.to_string() }),
-        Identifier(name) => result!(self::Identifier::new(scope_stack.get(name))),
+        String(ref s) => result!(StringLiteral { s: s.to_string() }),
+        // Locals shadow globals
+        Identifier(name) => match (scope_stack.lookup(name), globals.reference_global(name)) {
+            (Some(var_id), _) => result!(self::Identifier::new(var_id)),
+            (None, Some(global_id)) => {
+                if globals.is_global_initialised(global_id) {
+                    result!(GlobalVariable { global_id })
+                } else {
+                    failure!(StaticAnalysisError::GlobalReadBeforeInitialised(
+                        name.to_string(),
+                        expr.start,
+                        expr.end,
+                    ))
+                }
+            }
+            (None, None) => failure!(StaticAnalysisError::UndeclaredVariable(
+                name.to_string(),
+                closest_variable(globals, scope_stack, name),
+                expr.start,
+                expr.end,
+            )),
+        },
         BinaryOp(ref l, op, ref r) => {
             let lhs = expr!(l);
             let rhs = expr!(r);
-            macro_rules! int_op {
-                ( $op:tt ) => {
-                    result!(BinaryIntegerOp::new(lhs, rhs, |l, r| {l $op r}))
-                }
-            }
+            let site = CodeSite {
+                start: expr.start,
+                end: expr.end,
+            };
             macro_rules! cmp_op {
                 ( $op:tt ) => {
-                    result!(BinaryIntegerOp::new(lhs, rhs, |l, r| { if l $op r {1} else {0} }))
+                    result!(BinaryIntegerOp::new(lhs, rhs, |l, r| { Ok(if l $op r {1} else {0}) }, site))
                 };
             }
             macro_rules! bool_op {
@@ -325,16 +490,10 @@ pub fn build_expr<'a>(
                 };
             }
             match op {
-                Add => int_op!(+),
-                Sub => int_op!(-),
-                Mul => int_op!(*),
-                Div => int_op!(/),
-                Mod => int_op!(%),
-                LeftShift => int_op!(<<),
-                RightShift => int_op!(>>),
-                BitOr => int_op!(|),
-                BitAnd => int_op!(&),
-                BitXor => int_op!(^),
+                Add | Sub | Mul | Div | Mod | LeftShift | RightShift | BitOr | BitAnd | BitXor => {
+                    let func = integer_op(op, globals.checked_arithmetic());
+                    result!(BinaryIntegerOp::new(lhs, rhs, func, site))
+                }
                 LessThan => cmp_op!(<),
                 MoreThan => cmp_op!(>),
                 LessThanOrEqual => cmp_op!(<=),
@@ -345,19 +504,54 @@ pub fn build_expr<'a>(
                 BoolAnd => bool_op!(|v| { !v }),
             }
         }
-        Call(ref fname, ref argument_exprs) => {
+        Call(module, fname, ref argument_exprs) => {
             let argument_exprs = expr_list!(argument_exprs);
-            if let Some(func) = globals.reference_func(fname) {
-                result!(self::Call {
+            let qualified_name = match module {
+                Some(module) => format!("{}::{}", module, fname),
+                None => fname.to_string(),
+            };
+            match globals.reference_func(module, fname) {
+                Ok((_, parameters)) if parameters.len() != argument_exprs.len() => {
+                    failure!(StaticAnalysisError::WrongArgumentCount(
+                        qualified_name,
+                        parameters.to_vec(),
+                        argument_exprs.len(),
+                        expr.start,
+                        expr.end,
+                    ))
+                }
+                Ok((func, _)) => result!(self::Call {
                     func,
                     argument_exprs,
-                })
-            } else {
-                failure!(StaticAnalysisError::CallUnknownFunction(
-                    fname.to_string(),
-                    expr.start,
-                    expr.end,
-                ))
+                }),
+                Err(FunctionLookupError::UnknownModule) => {
+                    failure!(StaticAnalysisError::UnknownModule(
+                        module.unwrap_or_default().to_string(),
+                        expr.start,
+                        expr.end,
+                    ))
+                }
+                Err(FunctionLookupError::UnknownFunction) => {
+                    let suggestion = closest_name(fname, globals.callable_names(module)).map(
+                        |name| match module {
+                            Some(module) => format!("{}::{}", module, name),
+                            None => name,
+                        },
+                    );
+                    failure!(StaticAnalysisError::CallUnknownFunction(
+                        qualified_name,
+                        suggestion,
+                        expr.start,
+                        expr.end,
+                    ))
+                }
+                Err(FunctionLookupError::Private) => {
+                    failure!(StaticAnalysisError::CallPrivateFunction(
+                        qualified_name,
+                        expr.start,
+                        expr.end,
+                    ))
+                }
             }
         }
         Array(ref value_exprs) => result!(ArrayLiteral {
@@ -366,23 +560,47 @@ pub fn build_expr<'a>(
         Subscription(ref array_expr, ref index_expr) => result!(self::Subscription {
             array_expr: expr!(array_expr),
             index_expr: expr!(index_expr),
+            site: CodeSite {
+                start: expr.start,
+                end: expr.end,
+            },
         }),
+        // The lexer gives the magnitude of -2147483648 as i32::MIN, so negating the literal must
+        // give it back without overflowing
+        UnaryOp(ast::UnaryOpCode::Neg, ref ast_expr)
+            if matches!(ast_expr.kind, Number(i32::MIN)) =>
+        {
+            result!(IntegerLiteral { value: i32::MIN })
+        }
         UnaryOp(op, ref ast_expr) => {
             use ast::UnaryOpCode::*;
+            let site = CodeSite {
+                start: expr.start,
+                end: expr.end,
+            };
             let expr = expr!(ast_expr);
             macro_rules! int_op {
-                ( $op:tt ) => {
-                    result!(UnaryIntegerOp { expr, func: |v| {$op v} })
-                }
+                ( $func:expr ) => {
+                    result!(UnaryIntegerOp {
+                        expr,
+                        func: $func,
+                        site,
+                    })
+                };
             }
             match op {
                 BoolNot => result!(self::BoolNot { expr }),
-                BitNot => int_op!(!),
-                Neg => int_op!(-),
-                Plus => unimplemented!(),
+                BitNot => int_op!(|v: i32| Ok(!v)),
+                Neg if globals.checked_arithmetic() => {
+                    int_op!(|v: i32| v.checked_neg().ok_or(RuntimeFailureKind::Overflow))
+                }
+                Neg => int_op!(|v: i32| Ok(v.wrapping_neg())),
+                // Only checks that it's an int
+                Plus => int_op!(Ok),
             }
         }
-        Error => panic!("This really ought not have got this far"),
+        // The parse error has already been reported
+        Error => result!(BadExpr {}),
     }
 }
 
@@ -406,11 +624,45 @@ pub fn build_lexpr<'a>(
         }};
     }
     match &expr.kind {
-        Identifier(name) => result!(self::Identifier::new(scope_stack.get(name))),
+        Identifier(name) => match (scope_stack.lookup(name), globals.reference_global(name)) {
+            (Some(var_id), _) => result!(self::Identifier::new(var_id)),
+            (None, Some(global_id)) => {
+                if globals.is_global_mutable(name) {
+                    result!(GlobalVariable { global_id })
+                } else {
+                    failures.push(StaticAnalysisError::AssignToConst(
+                        name.to_string(),
+                        expr.start,
+                        expr.end,
+                    ));
+                    result!(BadExpr {})
+                }
+            }
+            (None, None) => {
+                failures.push(StaticAnalysisError::AssignToUndeclaredVariable(
+                    name.to_string(),
+                    closest_variable(globals, scope_stack, name),
+                    expr.start,
+                    expr.end,
+                ));
+                result!(BadExpr {})
+            }
+        },
         Subscription(array_expr, ref index_expr) => result!(self::Subscription {
             array_expr: expr!(array_expr),
             index_expr: expr!(index_expr),
+            site: CodeSite {
+                start: expr.start,
+                end: expr.end,
+            },
         }),
-        _ => panic! {"Not implemented or invalid l-expr for {:?} yet", expr},
+        // The parse error has already been reported
+        Error => result!(BadExpr {}),
+        _ => {
+            failures.push(StaticAnalysisError::InvalidAssignmentTarget(
+                expr.start, expr.end,
+            ));
+            result!(BadExpr {})
+        }
     }
 }
@@ -0,0 +1,112 @@
+use super::base::*;
+use super::error::{StaticAnalysisError, StaticAnalysisErrors};
+use super::visitor::{Declaration, Variable, Visitor, Walker};
+use lexer::Location;
+use std::collections::{HashMap, HashSet};
+
+/// The globals an expression or function reads and the functions it calls
+struct Reads<'a, 'g, 'io: 'g> {
+    globals: &'g Globals<'io>,
+    reads: HashMap<GlobalId, &'a str>,
+    calls: Vec<(FunctionId, &'a str, Location, Location)>,
+}
+
+impl<'a, 'g, 'io> Reads<'a, 'g, 'io> {
+    fn new(globals: &'g Globals<'io>) -> Self {
+        Reads {
+            globals,
+            reads: HashMap::new(),
+            calls: Vec::new(),
+        }
+    }
+}
+
+impl<'a, 'g, 'io> Visitor<'a> for Reads<'a, 'g, 'io> {
+    fn read(&mut self, variable: Variable<'a>) {
+        if let Variable::Global(name) = variable {
+            if let Some(id) = self.globals.reference_global(name) {
+                self.reads.insert(id, name);
+            }
+        }
+    }
+
+    fn call(&mut self, module: Option<&'a str>, name: &'a str, call: &'a ast::Expr) {
+        if let Ok((id, _)) = self.globals.reference_func(module, name) {
+            self.calls.push((id, name, call.start, call.end));
+        }
+    }
+}
+
+/// Every global the function can read, including through the functions it calls
+fn reachable_reads<'a>(
+    id: FunctionId,
+    functions: &HashMap<FunctionId, (HashMap<GlobalId, &'a str>, Vec<FunctionId>)>,
+) -> HashMap<GlobalId, &'a str> {
+    let mut reads = HashMap::new();
+    let mut reached = HashSet::new();
+    let mut to_visit = vec![id];
+    while let Some(id) = to_visit.pop() {
+        if !reached.insert(id) {
+            continue;
+        }
+        // Platform functions don't read globals
+        if let Some((direct_reads, calls)) = functions.get(&id) {
+            reads.extend(direct_reads);
+            to_visit.extend(calls);
+        }
+    }
+    reads
+}
+
+/// Initialisers are run in declaration order, so a function called by one mustn't be able to read
+/// a global declared at or after it. Direct reads are checked when the initialiser is built.
+pub fn check_initialiser_calls(
+    globals: &mut Globals,
+    programme: &[ast::Module],
+) -> StaticAnalysisErrors {
+    let mut functions = HashMap::new();
+    for module in programme {
+        globals.enter_module(module.file);
+        for (id, func) in module_functions(globals, module) {
+            let mut walker = Walker::new(Reads::new(globals));
+            for (arg, &(start, end)) in func.arguments.iter().zip(&func.argument_sites) {
+                walker.declare(arg, Declaration::Parameter(start, end));
+            }
+            walker.block(&func.stmts);
+            let reads = walker.visitor;
+            let calls = reads.calls.iter().map(|&(id, ..)| id).collect();
+            functions.insert(id, (reads.reads, calls));
+        }
+    }
+    let mut failures = StaticAnalysisErrors::new();
+    for module in programme {
+        globals.enter_module(module.file);
+        for item in &module.items {
+            let global = match *item {
+                ast::Item::Global(ref global) => global,
+                ast::Item::Function(_) => continue,
+            };
+            let global_id = match globals.declared_global(global) {
+                Some(global_id) => global_id,
+                None => continue,
+            };
+            let mut walker = Walker::new(Reads::new(globals));
+            walker.expr(&global.expr);
+            for &(id, fname, start, end) in &walker.visitor.calls {
+                let unset = reachable_reads(id, &functions)
+                    .into_iter()
+                    .filter(|&(read, _)| read >= global_id)
+                    .min();
+                if let Some((_, name)) = unset {
+                    failures.push(StaticAnalysisError::CallReadsGlobalBeforeInitialised(
+                        fname.to_string(),
+                        name.to_string(),
+                        start,
+                        end,
+                    ));
+                }
+            }
+        }
+    }
+    failures
+}
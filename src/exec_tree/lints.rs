@@ -0,0 +1,333 @@
+use super::base::*;
+use super::visitor::{Declaration, Variable, Visitor, Walker};
+use lexer::Location;
+use std::collections::{HashMap, HashSet};
+
+/// A check for code that can run but is probably a mistake
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Lint {
+    UnusedLocal,
+    UnusedParameter,
+    UnreachableFunction,
+    UnreachableCode,
+    InfiniteLoop,
+    NoEffect,
+}
+
+pub const LINTS: [Lint; 6] = [
+    Lint::UnusedLocal,
+    Lint::UnusedParameter,
+    Lint::UnreachableFunction,
+    Lint::UnreachableCode,
+    Lint::InfiniteLoop,
+    Lint::NoEffect,
+];
+
+impl Lint {
+    /// The name used to set the lint's level from the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::UnusedLocal => "unused_local",
+            Lint::UnusedParameter => "unused_parameter",
+            Lint::UnreachableFunction => "unreachable_function",
+            Lint::UnreachableCode => "unreachable_code",
+            Lint::InfiniteLoop => "infinite_loop",
+            Lint::NoEffect => "no_effect",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Lint> {
+        LINTS.iter().cloned().find(|lint| lint.name() == name)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LintLevel {
+    Allow,
+    Warn,
+    Deny,
+}
+
+/// Every lint warns unless it's set otherwise
+#[derive(Default)]
+pub struct LintLevels {
+    levels: HashMap<Lint, LintLevel>,
+}
+
+impl LintLevels {
+    pub fn get(&self, lint: Lint) -> LintLevel {
+        self.levels.get(&lint).cloned().unwrap_or(LintLevel::Warn)
+    }
+
+    pub fn set(&mut self, lint: Lint, level: LintLevel) {
+        self.levels.insert(lint, level);
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LintWarning {
+    UnusedLocal(String, Location, Location),
+    /// The parameter and its function, located at the parameter
+    UnusedParameter(String, String, Location, Location),
+    UnreachableFunction(String, Location, Location),
+    /// Located at the first statement after the `return`
+    UnreachableCode(Location, Location),
+    /// Located at the loop's condition
+    InfiniteLoop(Location, Location),
+    NoEffect(Location, Location),
+}
+
+impl LintWarning {
+    pub fn lint(&self) -> Lint {
+        match *self {
+            LintWarning::UnusedLocal(..) => Lint::UnusedLocal,
+            LintWarning::UnusedParameter(..) => Lint::UnusedParameter,
+            LintWarning::UnreachableFunction(..) => Lint::UnreachableFunction,
+            LintWarning::UnreachableCode(..) => Lint::UnreachableCode,
+            LintWarning::InfiniteLoop(..) => Lint::InfiniteLoop,
+            LintWarning::NoEffect(..) => Lint::NoEffect,
+        }
+    }
+}
+
+/// Loop variables and the entry point's parameter are tracked so they can shadow, but aren't
+/// linted
+enum LocalKind {
+    Parameter(Location, Location),
+    Declared(Location, Location),
+    LoopVariable,
+    EntryPointParameter,
+}
+
+struct Local<'a> {
+    name: &'a str,
+    kind: LocalKind,
+    used: bool,
+}
+
+struct FunctionLinter<'a, 'g, 'io: 'g> {
+    globals: &'g Globals<'io>,
+    /// Indexed by the walker's local numbers
+    locals: Vec<Local<'a>>,
+    is_entry_point: bool,
+    calls: HashSet<FunctionId>,
+    warnings: Vec<LintWarning>,
+}
+
+impl<'a, 'g, 'io> FunctionLinter<'a, 'g, 'io> {
+    fn new(globals: &'g Globals<'io>, is_entry_point: bool) -> Self {
+        FunctionLinter {
+            globals,
+            locals: Vec::new(),
+            is_entry_point,
+            calls: HashSet::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Variables starting with an underscore are taken to be unused on purpose
+    fn unused_locals(&mut self, func: &ast::Function) {
+        for local in &self.locals {
+            if local.used || local.name.starts_with('_') {
+                continue;
+            }
+            match local.kind {
+                LocalKind::Parameter(start, end) => {
+                    self.warnings.push(LintWarning::UnusedParameter(
+                        local.name.to_string(),
+                        func.name.clone(),
+                        start,
+                        end,
+                    ))
+                }
+                LocalKind::Declared(start, end) => {
+                    self.warnings
+                        .push(LintWarning::UnusedLocal(local.name.to_string(), start, end))
+                }
+                LocalKind::LoopVariable | LocalKind::EntryPointParameter => {}
+            }
+        }
+    }
+}
+
+impl<'a, 'g, 'io> Visitor<'a> for FunctionLinter<'a, 'g, 'io> {
+    fn declare(&mut self, name: &'a str, declaration: Declaration) {
+        let kind = match declaration {
+            // It's given the command line arguments whether it uses them or not
+            Declaration::Parameter(..) if self.is_entry_point => LocalKind::EntryPointParameter,
+            Declaration::Parameter(start, end) => LocalKind::Parameter(start, end),
+            Declaration::Let(start, end) => LocalKind::Declared(start, end),
+            Declaration::LoopVariable => LocalKind::LoopVariable,
+        };
+        self.locals.push(Local {
+            name,
+            kind,
+            used: false,
+        });
+    }
+
+    fn read(&mut self, variable: Variable<'a>) {
+        if let Variable::Local(local) = variable {
+            self.locals[local].used = true;
+        }
+    }
+
+    /// Updating a variable doesn't count as using it
+    fn compound_assign(&mut self, _variable: Variable<'a>) {}
+
+    fn call(&mut self, module: Option<&'a str>, name: &'a str, _call: &'a ast::Expr) {
+        if let Ok((id, _)) = self.globals.reference_func(module, name) {
+            self.calls.insert(id);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &'a ast::Statement) {
+        match *stmt {
+            ast::Statement::Expr(ref expr) if !has_call(expr) => {
+                self.warnings
+                    .push(LintWarning::NoEffect(expr.start, expr.end));
+            }
+            ast::Statement::While(ref test, ref block)
+                if is_always_true(test) && !can_leave_loop(block, false) =>
+            {
+                self.warnings
+                    .push(LintWarning::InfiniteLoop(test.start, test.end));
+            }
+            _ => {}
+        }
+    }
+
+    fn end_block(&mut self, stmts: &'a [ast::Statement]) {
+        let after_return = stmts
+            .iter()
+            .position(|stmt| matches!(*stmt, ast::Statement::Return(_)))
+            .and_then(|idx| stmts.get(idx + 1));
+        if let Some(stmt) = after_return {
+            let (start, end) = statement_span(stmt);
+            self.warnings.push(LintWarning::UnreachableCode(start, end));
+        }
+    }
+}
+
+fn has_call(expr: &ast::Expr) -> bool {
+    use ast::ExprKind::*;
+    match expr.kind {
+        Number(_) | Char(_) | String(_) | Identifier(_) | Error => false,
+        Array(ref exprs) => exprs.iter().any(has_call),
+        BinaryOp(ref lhs, _, ref rhs) | Subscription(ref lhs, ref rhs) => {
+            has_call(lhs) || has_call(rhs)
+        }
+        UnaryOp(_, ref expr) => has_call(expr),
+        Call(..) => true,
+    }
+}
+
+fn is_always_true(test: &ast::Expr) -> bool {
+    match test.kind {
+        ast::ExprKind::Number(n) => n != 0,
+        _ => false,
+    }
+}
+
+/// Whether there's a `return` anywhere in the loop, or a `break` that isn't in a nested loop
+fn can_leave_loop(stmts: &[ast::Statement], in_nested_loop: bool) -> bool {
+    use ast::Statement::*;
+    stmts.iter().any(|stmt| match *stmt {
+        Return(_) => true,
+        Break(..) => !in_nested_loop,
+        If(_, ref block, ref else_block) => {
+            can_leave_loop(block, in_nested_loop) || can_leave_loop(else_block, in_nested_loop)
+        }
+        While(_, ref block) | ForRange(_, _, _, ref block) | ForEach(_, _, ref block) => {
+            can_leave_loop(block, true)
+        }
+        Expr(_) | Assign(..) | CompoundAssign(..) | Declare(..) | Continue(..) => false,
+    })
+}
+
+/// Statements don't keep their own locations, so this is made from the parts that do
+fn statement_span(stmt: &ast::Statement) -> (Location, Location) {
+    use ast::Statement::*;
+    match *stmt {
+        Expr(ref expr) | Return(ref expr) => (expr.start, expr.end),
+        Assign(ref target, ref expr) | CompoundAssign(ref target, _, ref expr) => {
+            (target.start, expr.end)
+        }
+        Declare(start, _, _, ref expr) => (start, expr.end),
+        If(ref test, ..) | While(ref test, _) | ForEach(_, ref test, _) => (test.start, test.end),
+        ForRange(_, ref start, ref end, _) => (start.start, end.end),
+        Break(start, end) | Continue(start, end) => (start, end),
+    }
+}
+
+/// Run every lint over the programme, whatever their level. Functions are reachable from the
+/// script's entry point, from the public functions of the modules it imports and from the initialisers
+/// of globals.
+pub fn lint(
+    globals: &mut Globals,
+    programme: &[ast::Module],
+    entry_point: &str,
+) -> Vec<LintWarning> {
+    let mut warnings = Vec::new();
+    let mut calls: HashMap<FunctionId, HashSet<FunctionId>> = HashMap::new();
+    let mut reachable = Vec::new();
+    let mut functions = Vec::new();
+    let mut has_entry_point = false;
+    let mut all_parsed = true;
+    for (idx, module) in programme.iter().enumerate() {
+        globals.enter_module(module.file);
+        let is_script = idx + 1 == programme.len();
+        for item in &module.items {
+            match *item {
+                ast::Item::Global(ref global) => {
+                    let mut walker = Walker::new(FunctionLinter::new(globals, false));
+                    walker.expr(&global.expr);
+                    reachable.extend(walker.visitor.calls);
+                }
+                ast::Item::Function(ref func) => all_parsed &= !func.has_errors(),
+            }
+        }
+        // A statement that didn't parse could use any variable, so those functions aren't linted
+        for (id, func) in module_functions(globals, module)
+            .into_iter()
+            .filter(|&(_, func)| !func.has_errors())
+        {
+            let is_entry_point = is_script && func.name == entry_point;
+            let mut walker = Walker::new(FunctionLinter::new(globals, is_entry_point));
+            for (arg, &(start, end)) in func.arguments.iter().zip(&func.argument_sites) {
+                walker.declare(arg, Declaration::Parameter(start, end));
+            }
+            walker.block(&func.stmts);
+            let mut linter = walker.visitor;
+            linter.unused_locals(func);
+            warnings.extend(linter.warnings);
+            calls.entry(id).or_default().extend(linter.calls);
+            if is_entry_point {
+                has_entry_point = true;
+                reachable.push(id);
+            } else if !is_script && func.public {
+                reachable.push(id);
+            }
+            functions.push((id, func));
+        }
+    }
+    // Calls from functions that didn't parse aren't known
+    if has_entry_point && all_parsed {
+        let mut reached = HashSet::new();
+        while let Some(id) = reachable.pop() {
+            if reached.insert(id) {
+                reachable.extend(calls.get(&id).into_iter().flatten());
+            }
+        }
+        for (id, func) in functions {
+            if !reached.contains(&id) {
+                warnings.push(LintWarning::UnreachableFunction(
+                    func.name.clone(),
+                    func.name_start,
+                    func.name_end,
+                ));
+            }
+        }
+    }
+    warnings
+}
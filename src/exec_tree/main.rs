@@ -1,26 +1,185 @@
 use super::base::*;
 use super::error::*;
+use super::expressions::build_expr;
+use super::initialisers::check_initialiser_calls;
+use super::lints::{lint, LintLevel, LintLevels, LintWarning};
 use super::statements::build_block;
-use std::io::{Read, Write};
+use super::types::infer_types;
+use std::io::{self, Read, Write};
 
-fn collect_funcs(globals: &mut Globals, programme: &[ast::Function]) {
-    for func in programme {
-        globals.declare_func(func);
+/// Initialisers for the globals in the order they must be run
+type GlobalInitialisers = Vec<(GlobalId, ExprBox)>;
+
+/// Settings for the optional parts of static analysis and execution
+pub struct Options {
+    /// Report operations that type inference shows can only fail
+    pub infer_types: bool,
+    pub lints: LintLevels,
+    /// The function in the script to run, `main` by default
+    pub entry_point: String,
+    /// Report integer overflow as a runtime failure instead of wrapping
+    pub checked_arithmetic: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            infer_types: false,
+            lints: LintLevels::default(),
+            entry_point: "main".to_string(),
+            checked_arithmetic: false,
+        }
+    }
+}
+
+fn collect_items(globals: &mut Globals, programme: &[ast::Module]) -> StaticAnalysisErrors {
+    let mut failures = StaticAnalysisErrors::new();
+    for module in programme {
+        globals.declare_module(module);
+    }
+    for module in programme {
+        globals.enter_module(module.file);
+        for item in &module.items {
+            match *item {
+                ast::Item::Function(ref func) => {
+                    if let Err(failure) = globals.declare_func(func) {
+                        failures.push(failure);
+                    }
+                }
+                ast::Item::Global(ref global) => {
+                    if let Err(failure) = globals.declare_global(global) {
+                        failures.push(failure);
+                    }
+                }
+            }
+        }
+    }
+    failures
+}
+
+/// The entry point is looked for in the script, which is the last module
+fn check_entry_point(
+    globals: &Globals,
+    programme: &[ast::Module],
+    name: &str,
+) -> Option<StaticAnalysisError> {
+    let script = programme.last().expect("No modules given").file;
+    match globals.get_entry_point(script, name) {
+        None => Some(StaticAnalysisError::MissingEntryPoint(
+            name.to_string(),
+            script,
+        )),
+        Some((_, parameters, site)) if parameters.len() > 1 => {
+            Some(StaticAnalysisError::EntryPointParameters(
+                name.to_string(),
+                parameters.len(),
+                site.start,
+                site.end,
+            ))
+        }
+        Some(_) => None,
+    }
+}
+
+/// Declare everything and run the analysis that doesn't need the programme to be built. Lints set
+/// to deny are failures and those set to warn are given to `warn`.
+fn analyse_programme(
+    globals: &mut Globals,
+    programme: &[ast::Module],
+    options: &Options,
+    warn: &mut dyn FnMut(Vec<LintWarning>),
+) -> StaticAnalysisErrors {
+    globals.set_checked_arithmetic(options.checked_arithmetic);
+    let mut failures = collect_items(globals, programme);
+    failures.extend(check_entry_point(globals, programme, &options.entry_point));
+    failures.extend(check_initialiser_calls(globals, programme));
+    let mut warnings = Vec::new();
+    for warning in lint(globals, programme, &options.entry_point) {
+        match options.lints.get(warning.lint()) {
+            LintLevel::Allow => {}
+            LintLevel::Warn => warnings.push(warning),
+            LintLevel::Deny => failures.push(StaticAnalysisError::DeniedLint(warning)),
+        }
+    }
+    warn(warnings);
+    if options.infer_types {
+        failures.extend(infer_types(globals, programme));
     }
+    failures
+}
+
+fn functions<'a, 'b>(items: &'b [ast::Item<'a>]) -> impl Iterator<Item = &'b ast::Function<'a>> {
+    items.iter().filter_map(|item| match *item {
+        ast::Item::Function(ref func) => Some(func),
+        ast::Item::Global(_) => None,
+    })
 }
 
-fn build_funcs(globals: &mut Globals, programme: &[ast::Function]) -> ExecResult<()> {
+/// Globals are built in declaration order, so an initialiser can only read the globals before it
+fn build_globals<'a>(
+    globals: &mut Globals,
+    items: &'a [ast::Item],
+) -> BuildResult<'a, GlobalInitialisers> {
+    let mut initialisers = Vec::new();
     let mut failures = StaticAnalysisErrors::new();
-    for func in programme {
-        let ((stmts, max_locals), func_failures) = build_func(globals, func);
-        globals.define_func(&func.name, stmts, max_locals);
-        failures.extend(func_failures);
+    for item in items {
+        if let ast::Item::Global(ref global) = *item {
+            let (expr, expr_failures) = build_expr(globals, &ScopeStack::new(), &global.expr);
+            failures.extend(expr_failures);
+            // Duplicates are still built for their failures, but are never run
+            if let Some(global_id) = globals.declared_global(global) {
+                globals.mark_global_initialised(global_id);
+                initialisers.push((global_id, expr));
+            }
+        }
+    }
+    (initialisers, failures)
+}
+
+/// Modules are built in the order given, which must match the order they were declared in. Any
+/// failures from declaring them are reported along with those from building.
+fn build_programme(
+    globals: &mut Globals,
+    programme: &[ast::Module],
+    mut failures: StaticAnalysisErrors,
+) -> ExecResult<GlobalInitialisers> {
+    let mut initialisers = Vec::new();
+    for module in programme {
+        globals.enter_module(module.file);
+        let (module_initialisers, global_failures) = build_globals(globals, &module.items);
+        initialisers.extend(module_initialisers);
+        failures.extend(global_failures);
+        for func in functions(&module.items) {
+            let ((stmts, max_locals), func_failures) = build_func(globals, func);
+            globals.define_func(func, stmts, max_locals);
+            if func.has_errors() {
+                // A statement that didn't parse may have declared the variables it seems to use
+                failures.extend(func_failures.into_iter().filter(|failure| {
+                    !matches!(
+                        *failure,
+                        StaticAnalysisError::UndeclaredVariable(..)
+                            | StaticAnalysisError::AssignToUndeclaredVariable(..)
+                    )
+                }));
+            } else {
+                failures.extend(func_failures);
+            }
+        }
     }
     if !failures.is_empty() {
         Err(ExecError::StaticAnalysisFailed(failures))
     } else {
-        Ok(())
+        Ok(initialisers)
+    }
+}
+
+fn initialise_globals(globals: &Globals, initialisers: &GlobalInitialisers) -> ExecResult<()> {
+    let locals = Locals { vars: Vec::new() };
+    for &(global_id, ref expr) in initialisers {
+        let value = expr.evaluate(globals, &locals)?;
+        globals.set_global(global_id, value);
     }
+    Ok(())
 }
 
 fn build_func<'a>(
@@ -31,7 +190,7 @@ fn build_func<'a>(
     for arg in &func.arguments {
         scope_stack.declare(arg);
     }
-    let (stmts, failures) = build_block(globals, &mut scope_stack, &func.stmts);
+    let (stmts, failures) = build_block(globals, &mut scope_stack, &func.stmts, false);
     ((stmts, scope_stack.get_max_locals()), failures)
 }
 
@@ -49,21 +208,58 @@ fn convert_args_to_values(args: Vec<String>) -> Value {
     )
 }
 
+/// Run static analysis without executing, for programmes that can't be run. The warnings are
+/// given to `warn` before any failures are returned.
+pub fn check(
+    programme: &[ast::Module],
+    options: &Options,
+    warn: &mut dyn FnMut(Vec<LintWarning>),
+) -> ExecResult<()> {
+    let mut input = io::empty();
+    let mut output = io::sink();
+    let mut globals = Globals::new(&mut input, &mut output);
+    let failures = analyse_programme(&mut globals, programme, options, warn);
+    build_programme(&mut globals, programme, failures).map(|_| ())
+}
+
+/// Globals are initialised in the order they're declared before the entry point is called. Modules
+/// come after the modules they import, so the entry point is looked for in the last module. It's
+/// given the command line arguments if it takes a parameter. The lint warnings are given to `warn`
+/// before anything is run.
 pub fn exec<'a>(
-    programme: &'a [ast::Function],
+    programme: &'a [ast::Module],
+    options: &Options,
     args: Vec<String>,
     input: &mut dyn Read,
     output: &mut dyn Write,
+    warn: &mut dyn FnMut(Vec<LintWarning>),
 ) -> ExecResult<i32> {
     let mut globals = Globals::new(input, output);
-    collect_funcs(&mut globals, programme);
-    assert!(globals.has_main(), "No main function defined");
-    build_funcs(&mut globals, programme)?;
-    {
-        let main_func = globals.get_main();
-        match main_func.call(&globals, vec![convert_args_to_values(args)])? {
-            Value::Integer(status_code) => Ok(status_code),
-            Value::Array(_) => panic!("Array returned from main. Requires int."),
-        }
+    let failures = analyse_programme(&mut globals, programme, options, warn);
+    let initialisers = build_programme(&mut globals, programme, failures)?;
+    initialise_globals(&globals, &initialisers)?;
+    let script = programme.last().expect("No modules given").file;
+    let (func_id, parameters, site) = globals
+        .get_entry_point(script, &options.entry_point)
+        .expect("The entry point is checked by static analysis");
+    let args = if parameters.is_empty() {
+        Vec::new()
+    } else {
+        vec![convert_args_to_values(args)]
+    };
+    match globals.lookup_func(func_id).call(&globals, args)? {
+        Value::Integer(status_code) => Ok(status_code),
+        // Reported at the signature as the return that gave the array is no longer known
+        Value::Array(_) => Err(ExecError::RuntimeFailure(
+            RuntimeFailureKind::EntryPointReturnedArray(options.entry_point.clone()),
+            vec![StackFrame {
+                site,
+                function: Some(format!(
+                    "{}({})",
+                    options.entry_point,
+                    parameters.join(", ")
+                )),
+            }],
+        )),
     }
 }
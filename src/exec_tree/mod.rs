@@ -1,10 +1,15 @@
 mod base;
 pub mod error;
 mod expressions;
+mod initialisers;
+pub mod lints;
 mod main;
 mod statements;
+mod suggest;
+mod types;
+mod visitor;
 
-pub use self::main::exec;
+pub use self::main::{check, exec, Options};
 
 #[cfg(test)]
 mod tests;
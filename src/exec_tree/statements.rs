@@ -1,6 +1,9 @@
 use super::base::*;
-use super::error::BuildResult;
-use super::expressions::{build_expr, build_lexpr, evaluate_to_bool, Identifier};
+use super::error::{runtime_failure, BuildResult, RuntimeFailureKind, StaticAnalysisError};
+use super::expressions::{
+    build_expr, build_lexpr, evaluate_to_bool, evaluate_to_int, integer_op, Identifier,
+    IntegerOpResult,
+};
 use exec_tree::error::ExecResult;
 
 struct Return {
@@ -28,6 +31,32 @@ impl Statement for Assign {
     }
 }
 
+struct CompoundAssign {
+    lexpr: Box<dyn LExpr>,
+    lexpr_site: CodeSite,
+    rexpr: ExprBox,
+    func: fn(i32, i32) -> IntegerOpResult,
+    /// The whole statement, for when the operation fails
+    site: CodeSite,
+}
+
+impl Statement for CompoundAssign {
+    fn do_stmt(&self, globals: &Globals, locals: &mut Locals) -> ExecResult<FunctionState> {
+        // Like rust the right hand side is evaluated before the target
+        let rhs = evaluate_to_int(globals, locals, &self.rexpr)?;
+        self.lexpr.modify(globals, locals, &|value| match value {
+            Value::Integer(lhs) => (self.func)(lhs, rhs)
+                .map(Value::Integer)
+                .map_err(|kind| runtime_failure(kind, self.site)),
+            Value::Array(_) => Err(runtime_failure(
+                RuntimeFailureKind::ExpectedIntGotArray,
+                self.lexpr_site,
+            )),
+        })?;
+        Ok(FunctionState::NoReturn)
+    }
+}
+
 struct ExprStatement {
     expr: ExprBox,
 }
@@ -42,6 +71,7 @@ impl Statement for ExprStatement {
 struct IfStatement {
     expr: ExprBox,
     stmts: Vec<Box<dyn Statement>>,
+    else_stmts: Vec<Box<dyn Statement>>,
 }
 
 impl Statement for IfStatement {
@@ -49,7 +79,7 @@ impl Statement for IfStatement {
         if evaluate_to_bool(globals, locals, &self.expr)? {
             exec_block(globals, locals, &self.stmts)
         } else {
-            Ok(FunctionState::NoReturn)
+            exec_block(globals, locals, &self.else_stmts)
         }
     }
 }
@@ -59,21 +89,115 @@ struct WhileStatement {
     stmts: Vec<Box<dyn Statement>>,
 }
 
+/// Run one pass of a loop body. Gives the state to leave the loop with if it should stop.
+fn exec_loop_body(
+    globals: &Globals,
+    locals: &mut Locals,
+    stmts: &[Box<dyn Statement>],
+) -> ExecResult<Option<FunctionState>> {
+    Ok(match exec_block(globals, locals, stmts)? {
+        FunctionState::Return(v) => Some(FunctionState::Return(v)),
+        FunctionState::Break => Some(FunctionState::NoReturn),
+        FunctionState::NoReturn | FunctionState::Continue => None,
+    })
+}
+
 impl Statement for WhileStatement {
     fn do_stmt(&self, globals: &Globals, locals: &mut Locals) -> ExecResult<FunctionState> {
         while evaluate_to_bool(globals, locals, &self.expr)? {
-            if let FunctionState::Return(v) = exec_block(globals, locals, &self.stmts)? {
-                return Ok(FunctionState::Return(v));
+            if let Some(state) = exec_loop_body(globals, locals, &self.stmts)? {
+                return Ok(state);
             }
         }
         Ok(FunctionState::NoReturn)
     }
 }
 
+struct ForRangeStatement {
+    var_id: usize,
+    start: ExprBox,
+    end: ExprBox,
+    stmts: Vec<Box<dyn Statement>>,
+}
+
+impl Statement for ForRangeStatement {
+    fn do_stmt(&self, globals: &Globals, locals: &mut Locals) -> ExecResult<FunctionState> {
+        let start = evaluate_to_int(globals, locals, &self.start)?;
+        let end = evaluate_to_int(globals, locals, &self.end)?;
+        for i in start..end {
+            locals.vars[self.var_id] = Value::Integer(i);
+            if let Some(state) = exec_loop_body(globals, locals, &self.stmts)? {
+                return Ok(state);
+            }
+        }
+        Ok(FunctionState::NoReturn)
+    }
+}
+
+struct ForEachStatement {
+    var_id: usize,
+    expr: ExprBox,
+    stmts: Vec<Box<dyn Statement>>,
+}
+
+impl Statement for ForEachStatement {
+    fn do_stmt(&self, globals: &Globals, locals: &mut Locals) -> ExecResult<FunctionState> {
+        let array = match self.expr.evaluate(globals, locals)? {
+            Value::Array(array) => array,
+            Value::Integer(_) => {
+                return Err(runtime_failure(
+                    RuntimeFailureKind::ExpectedArrayGotInt,
+                    self.expr.site,
+                ))
+            }
+        };
+        let len = array.borrow().len();
+        for idx in 0..len {
+            // Take a copy so that the body is free to assign to the array
+            locals.vars[self.var_id] = array.borrow()[idx].clone();
+            if let Some(state) = exec_loop_body(globals, locals, &self.stmts)? {
+                return Ok(state);
+            }
+        }
+        Ok(FunctionState::NoReturn)
+    }
+}
+
+struct Break;
+
+impl Statement for Break {
+    fn do_stmt(&self, _globals: &Globals, _locals: &mut Locals) -> ExecResult<FunctionState> {
+        Ok(FunctionState::Break)
+    }
+}
+
+struct Continue;
+
+impl Statement for Continue {
+    fn do_stmt(&self, _globals: &Globals, _locals: &mut Locals) -> ExecResult<FunctionState> {
+        Ok(FunctionState::Continue)
+    }
+}
+
+/// `in_loop` is whether `break` and `continue` are allowed in the block. Variables declared in the
+/// block go out of scope at its end.
 pub fn build_block<'a>(
     globals: &Globals,
     scope_stack: &mut ScopeStack,
     stmts: &'a [ast::Statement],
+    in_loop: bool,
+) -> BuildResult<'a, Vec<Box<dyn Statement>>> {
+    scope_stack.push_scope();
+    let result = build_block_stmts(globals, scope_stack, stmts, in_loop);
+    scope_stack.pop_scope();
+    result
+}
+
+fn build_block_stmts<'a>(
+    globals: &Globals,
+    scope_stack: &mut ScopeStack,
+    stmts: &'a [ast::Statement],
+    in_loop: bool,
 ) -> BuildResult<'a, Vec<Box<dyn Statement>>> {
     let mut rv: Vec<Box<dyn Statement>> = Vec::with_capacity(stmts.len());
     let mut failures = Vec::new();
@@ -90,8 +214,8 @@ pub fn build_block<'a>(
         };
     }
     macro_rules! block {
-        ( $stmts:expr ) => {{
-            let (stmts, inner_failures) = build_block(globals, scope_stack, $stmts);
+        ( $stmts:expr, $in_loop:expr ) => {{
+            let (stmts, inner_failures) = build_block(globals, scope_stack, $stmts, $in_loop);
             failures.extend(inner_failures);
             stmts
         }};
@@ -99,11 +223,13 @@ pub fn build_block<'a>(
     for stmt in stmts {
         match *stmt {
             ast::Statement::Return(ref expr) => stmt!(Return { expr: expr!(expr) }),
-            ast::Statement::Declare(name, ref expr) => {
+            ast::Statement::Declare(_, name, _, ref expr) => {
+                // Built before declaring so that `let a = a + 1;` can refer to a shadowed `a`
+                let rexpr = expr!(expr);
                 let var_id = scope_stack.declare(name);
                 stmt!(Assign {
                     lexpr: Box::new(Identifier::new(var_id)),
-                    rexpr: expr!(expr),
+                    rexpr,
                 })
             }
             ast::Statement::Assign(ref lexpr, ref rexpr) => {
@@ -114,15 +240,69 @@ pub fn build_block<'a>(
                     rexpr: expr!(rexpr),
                 })
             }
+            ast::Statement::CompoundAssign(ref lexpr, op, ref rexpr) => {
+                let (lexpr_box, inner_failures) = build_lexpr(globals, scope_stack, lexpr);
+                failures.extend(inner_failures);
+                stmt!(CompoundAssign {
+                    lexpr: lexpr_box,
+                    lexpr_site: CodeSite {
+                        start: lexpr.start,
+                        end: lexpr.end,
+                    },
+                    rexpr: expr!(rexpr),
+                    func: integer_op(op, globals.checked_arithmetic()),
+                    site: CodeSite {
+                        start: lexpr.start,
+                        end: rexpr.end,
+                    },
+                })
+            }
             ast::Statement::Expr(ref expr) => stmt!(ExprStatement { expr: expr!(expr) }),
-            ast::Statement::If(ref expr, ref stmts) => stmt!(IfStatement {
+            ast::Statement::If(ref expr, ref stmts, ref else_stmts) => stmt!(IfStatement {
                 expr: expr!(expr),
-                stmts: block!(stmts)
+                stmts: block!(stmts, in_loop),
+                else_stmts: block!(else_stmts, in_loop)
             }),
             ast::Statement::While(ref expr, ref stmts) => stmt!(WhileStatement {
                 expr: expr!(expr),
-                stmts: block!(stmts)
+                stmts: block!(stmts, true)
             }),
+            ast::Statement::ForRange(name, ref start, ref end, ref stmts) => {
+                // Bounds are built before declaring the variable so they can't refer to it, and the
+                // variable is only in scope for the loop
+                let start = expr!(start);
+                let end = expr!(end);
+                scope_stack.push_scope();
+                stmt!(ForRangeStatement {
+                    var_id: scope_stack.declare(name),
+                    start,
+                    end,
+                    stmts: block!(stmts, true)
+                });
+                scope_stack.pop_scope();
+            }
+            ast::Statement::ForEach(name, ref array, ref stmts) => {
+                let expr = expr!(array);
+                scope_stack.push_scope();
+                stmt!(ForEachStatement {
+                    var_id: scope_stack.declare(name),
+                    expr,
+                    stmts: block!(stmts, true)
+                });
+                scope_stack.pop_scope();
+            }
+            ast::Statement::Break(start, end) => {
+                if !in_loop {
+                    failures.push(StaticAnalysisError::BreakOutsideLoop(start, end));
+                }
+                stmt!(Break)
+            }
+            ast::Statement::Continue(start, end) => {
+                if !in_loop {
+                    failures.push(StaticAnalysisError::ContinueOutsideLoop(start, end));
+                }
+                stmt!(Continue)
+            }
         }
     }
     (rv, failures)
@@ -0,0 +1,67 @@
+/// Levenshtein distance between two strings, counted in chars
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, a_char) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, &b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + if a_char == b_char { 0 } else { 1 };
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// Find the candidate that `name` was most likely a typo of. Case is ignored, and a candidate must
+/// be within a third of the name's length in edits to count, so short names only match if they
+/// differ by case. Ties go to the first alphabetically.
+pub fn closest_name<'a, I>(name: &str, candidates: I) -> Option<String>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let name_lower = name.to_lowercase();
+    let max_distance = name.chars().count() / 3;
+    candidates
+        .into_iter()
+        .filter(|&candidate| candidate != name)
+        .map(|candidate| {
+            let distance = edit_distance(&name_lower, &candidate.to_lowercase());
+            (distance, candidate)
+        })
+        .filter(|&(distance, _)| distance <= max_distance)
+        .min()
+        .map(|(_, candidate)| candidate.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("same", "same"), 0);
+    }
+
+    #[test]
+    fn prefers_case_insensitive_match() {
+        let candidates = vec!["dict_size", "dict_size_set", "dict_size_get"];
+        assert_eq!(
+            closest_name("dict_size_Set", candidates),
+            Some("dict_size_set".to_string())
+        );
+    }
+
+    #[test]
+    fn ignores_distant_names() {
+        assert_eq!(closest_name("foo", vec!["bar", "baz"]), None);
+        assert_eq!(closest_name("a", vec!["b", "A"]), Some("A".to_string()));
+        assert_eq!(
+            closest_name("lenght", vec!["len", "length"]),
+            Some("length".to_string())
+        );
+    }
+}
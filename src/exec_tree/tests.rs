@@ -1,14 +1,97 @@
 use super::super::grammar::parse_Programme;
 use super::super::lexer::{Location, Matcher};
+use super::base::{ast, CodeSite};
 use super::error::*;
-use super::main::exec;
-use file_data::FileHandle;
+use super::lints::*;
+use super::main::{check, exec, Options};
+use file_data::{FileData, FileHandle};
+use std::io;
 
 struct ProgResult {
     status_code: i32,
     output: Vec<u8>,
 }
 
+/// Wrap the items of a single file up as the whole programme
+fn script(file: FileHandle, items: Vec<ast::Item>) -> Vec<ast::Module> {
+    vec![ast::Module {
+        file,
+        name: "main".to_string(),
+        imports: Vec::new(),
+        prelude: false,
+        items,
+    }]
+}
+
+fn parse_script(file: FileHandle, text: &str) -> Vec<ast::Module<'_>> {
+    script(
+        file,
+        parse_Programme(&mut Vec::new(), Matcher::new(file, text)).unwrap(),
+    )
+}
+
+/// Parse each `(name, imports, text)` as a module, the first is the prelude and the last the script
+fn parse_modules(modules: &[(&str, &[&str], &'static str)]) -> Vec<ast::Module<'static>> {
+    let mut files = FileData::new();
+    let mut programme: Vec<ast::Module> = Vec::new();
+    for (i, &(name, imports, text)) in modules.iter().enumerate() {
+        let file = files.add(format!("{}.sl", name), text.to_string());
+        let imports = imports
+            .iter()
+            .map(|&import| programme.iter().find(|m| m.name == import).unwrap().file)
+            .collect();
+        programme.push(ast::Module {
+            file,
+            name: name.to_string(),
+            imports,
+            prelude: i == 0,
+            items: parse_Programme(&mut Vec::new(), Matcher::new(file, text)).unwrap(),
+        });
+    }
+    programme
+}
+
+/// The warnings from static analysis, whether or not it fails
+fn warnings(prog: &[ast::Module], options: &Options) -> Vec<LintWarning> {
+    let mut warnings = Vec::new();
+    let _ = check(prog, options, &mut |found| warnings = found);
+    warnings
+}
+
+/// Run a programme on a single line of ascii, so that the char and byte offsets of its sites match
+fn run_line_to_failure(text: &str) -> ExecError {
+    let prog = parse_script(FileHandle::dummy(), text);
+    let mut output = Vec::new();
+    let mut input: &'static [u8] = &[];
+    exec(
+        &prog,
+        &Options::default(),
+        Vec::new(),
+        &mut input,
+        &mut output,
+        &mut |_| {},
+    )
+    .unwrap_err()
+}
+
+/// The frames are given innermost first with the function each is in
+fn runtime_failure_at(kind: RuntimeFailureKind, frames: &[(usize, usize, &str)]) -> ExecError {
+    let file = FileHandle::dummy();
+    ExecError::RuntimeFailure(
+        kind,
+        frames
+            .iter()
+            .map(|&(start, end, function)| StackFrame {
+                site: CodeSite {
+                    start: Location::new(file, 1, start, start),
+                    end: Location::new(file, 1, end, end),
+                },
+                function: Some(function.to_string()),
+            })
+            .collect(),
+    )
+}
+
 fn compile_and_run_programme(text: &str) -> ProgResult {
     compile_and_run_programme_with_args_and_input(text, Vec::new(), &[])
 }
@@ -26,9 +109,18 @@ fn compile_and_run_programme_with_args_and_input(
     args: Vec<String>,
     mut input: &'static [u8],
 ) -> ProgResult {
-    let prog = parse_Programme(Matcher::new(FileHandle::dummy(), text)).unwrap();
+    let prog = parse_script(FileHandle::dummy(), text);
     let mut output = Vec::new();
-    let status_code = { exec(&prog, args, &mut input, &mut output) };
+    let status_code = {
+        exec(
+            &prog,
+            &Options::default(),
+            args,
+            &mut input,
+            &mut output,
+            &mut |_| {},
+        )
+    };
     ProgResult {
         status_code: status_code.unwrap(),
         output: output,
@@ -69,6 +161,13 @@ test_return_expr! {return_expression, "2 + 3", 5}
 test_return_expr! {return_more_maths, "(2 * 5 - 1) % 5", 4}
 test_return_expr! {return_division, "5 / 2", 2}
 test_return_expr! {return_bit_manipulation, "1 << 2 | 64 >> 3 | 255 & 64 | 255 - 32 ^ 255", 0x6c}
+test_return_expr! {addition_wraps, "2147483647 + 1", -2147483648}
+test_return_expr! {multiplication_wraps, "65536 * 65536 + 3", 3}
+test_return_expr! {division_wraps, "-2147483648 / -1", -2147483648}
+test_return_expr! {shift_count_is_masked, "1 << 33 | -8 >> 34", -2 | 2}
+test_return_expr! {return_hex_binary_and_octal, "0xF0 | 0b1010 | 0o1", 0xFB}
+test_return_expr! {return_digit_separated, "1_000 * 1_000", 1_000_000}
+test_return_expr! {return_min_integer, "-2147483648", i32::MIN}
 
 #[test]
 fn declare_and_return() {
@@ -97,6 +196,97 @@ fn declare_update_and_return() {
     assert_eq!(result.status_code, 43);
 }
 
+#[test]
+fn compound_assignment() {
+    let result = compile_and_run_programme(
+        "\
+            function main (args) {
+                let a = 6;
+                a += 4;
+                putc(a);
+                a -= 1;
+                putc(a);
+                a *= 3;
+                putc(a);
+                a /= 2;
+                putc(a);
+                a %= 5;
+                putc(a);
+                a <<= 3;
+                putc(a);
+                a >>= 1;
+                putc(a);
+                a |= 3;
+                putc(a);
+                a &= 5;
+                putc(a);
+                a ^= 6;
+                return a;
+            }
+        ",
+    );
+    assert_eq!(result.output, &[10, 9, 27, 13, 3, 24, 12, 15, 5]);
+    assert_eq!(result.status_code, 3);
+}
+
+#[test]
+fn compound_assignment_evaluates_subscription_once() {
+    let result = compile_and_run_programme(
+        "\
+            function index(i) {
+                putc('i');
+                return i;
+            }
+            function main (args) {
+                let a = [1, 2, 3];
+                a[index(1)] += 40;
+                return a[1];
+            }
+        ",
+    );
+    assert_eq!(result.output, b"i");
+    assert_eq!(result.status_code, 42);
+}
+
+#[test]
+fn constants_and_globals() {
+    let result = compile_and_run_programme(
+        "\
+            const BASE = 10;
+            global counter = BASE * 2;
+            function count() {
+                counter += 1;
+                return counter;
+            }
+            function main (args) {
+                count();
+                let BASE = 1;
+                return count() + BASE;
+            }
+        ",
+    );
+    assert_eq!(result.status_code, 23);
+}
+
+#[test]
+fn globals_are_initialised_in_order() {
+    let result = compile_and_run_programme(
+        "\
+            global a = f('a', 1);
+            function main (args) {
+                return a + b;
+            }
+            global b = f('b', a * 10);
+            function f(c, v) {
+                putc(c);
+                return v;
+            }
+        ",
+    );
+    assert_eq!(result.output, b"ab");
+    assert_eq!(result.status_code, 11);
+}
+
 #[test]
 fn function_call() {
     let result = compile_and_run_programme(
@@ -296,6 +486,52 @@ fn if_statement_early_return() {
     assert_eq!(result.status_code, 10);
 }
 
+#[test]
+fn if_else_statement() {
+    let result = compile_and_run_programme(
+        "\
+            function main (args) {
+                if 1 < 4 {
+                    putc('a');
+                } else {
+                    putc('b');
+                }
+                if 1 > 4 {
+                    putc('c');
+                } else {
+                    putc('d');
+                }
+            }
+        ",
+    );
+    assert_eq!(result.output, b"ad");
+}
+
+#[test]
+fn else_if_chain() {
+    let result = compile_and_run_programme(
+        "\
+            function classify(n) {
+                if n < 0 {
+                    putc('-');
+                } else if n == 0 {
+                    putc('0');
+                } else if n < 10 {
+                    putc('s');
+                } else {
+                    return 1;
+                }
+                return 0;
+            }
+            function main (args) {
+                return classify(-5) + classify(0) + classify(3) + classify(50);
+            }
+        ",
+    );
+    assert_eq!(result.output, b"-0s");
+    assert_eq!(result.status_code, 1);
+}
+
 #[test]
 fn while_statement() {
     let result = compile_and_run_programme(
@@ -335,6 +571,139 @@ fn while_statement_early_return() {
     assert_eq!(result.status_code, 6);
 }
 
+#[test]
+fn break_and_continue() {
+    let result = compile_and_run_programme(
+        "\
+            function main (args) {
+                let i = 0;
+                while 1 {
+                    i = i + 1;
+                    if i % 2 == 0 {
+                        continue;
+                    }
+                    if i > 7 {
+                        break;
+                    }
+                    putc('a' + i);
+                }
+                return i;
+            }
+        ",
+    );
+    assert_eq!(result.output, b"bdfh");
+    assert_eq!(result.status_code, 9);
+}
+
+#[test]
+fn break_only_leaves_innermost_loop() {
+    let result = compile_and_run_programme(
+        "\
+            function main (args) {
+                let i = 0;
+                while i < 3 {
+                    while 1 {
+                        putc('a' + i);
+                        break;
+                    }
+                    i = i + 1;
+                }
+            }
+        ",
+    );
+    assert_eq!(result.output, b"abc");
+}
+
+#[test]
+fn for_range_statement() {
+    let result = compile_and_run_programme(
+        "\
+            function main (args) {
+                let total = 0;
+                for i in 0..5 {
+                    putc('a' + i);
+                    total = total + i;
+                }
+                for i in 3..1 {
+                    putc('x');
+                }
+                return total;
+            }
+        ",
+    );
+    assert_eq!(result.output, b"abcde");
+    assert_eq!(result.status_code, 10);
+}
+
+#[test]
+fn for_range_bounds_are_evaluated_once() {
+    let result = compile_and_run_programme(
+        "\
+            function main (args) {
+                let n = 3;
+                for i in 0..n {
+                    n = n + 1;
+                    putc('a' + i);
+                }
+                return n;
+            }
+        ",
+    );
+    assert_eq!(result.output, b"abc");
+    assert_eq!(result.status_code, 6);
+}
+
+#[test]
+fn for_each_statement() {
+    let result = compile_and_run_programme(
+        r#"
+            function main (args) {
+                let s = "hello";
+                for c in s {
+                    if c == 'l' {
+                        continue;
+                    }
+                    putc(c);
+                }
+                for c in s {
+                    if c == 'l' {
+                        return c;
+                    }
+                }
+            }
+        "#,
+    );
+    assert_eq!(result.output, b"heo");
+    assert_eq!(result.status_code, b'l' as i32);
+}
+
+#[test]
+fn for_each_over_int_is_a_runtime_failure() {
+    let text = r#"
+function main() {
+    for x in 5 {
+    }
+}
+    "#;
+    let file = FileHandle::dummy();
+    let prog = parse_script(file, text);
+    let mut output = Vec::new();
+    let mut input: &'static [u8] = &[];
+    let err = exec(
+        &prog,
+        &Options::default(),
+        Vec::new(),
+        &mut input,
+        &mut output,
+        &mut |_| {},
+    )
+    .unwrap_err();
+    match err {
+        ExecError::RuntimeFailure(RuntimeFailureKind::ExpectedArrayGotInt, _) => {}
+        _ => panic!("Unexpected error {:?}", err),
+    }
+}
+
 test_return_expr! {len_for_array_returns_array_length, "len(new(6))", 6}
 test_return_expr! {len_for_int_returns_minus_1, "len(6)", -1}
 
@@ -379,11 +748,31 @@ fn string_literal_mutability_and_independance() {
     assert_eq!(result.output, b"xbcaxcabx");
 }
 
+#[test]
+fn escape_sequences_in_literals() {
+    let result = compile_and_run_programme(
+        r#"
+            function main (args) {
+                let i = 0;
+                let s = "a\tb\x7f\"\\";
+                while i < len(s) {
+                    putc(s[i]);
+                    i = i + 1;
+                }
+                putc('\n');
+                return '\u{3b1}';
+            }
+        "#,
+    );
+    assert_eq!(result.output, b"a\tb\x7f\"\\\n");
+    assert_eq!(result.status_code, 0x3b1);
+}
+
 test_return_expr! {bool_not_converts_positive_to_0, "not 5", 0}
 test_return_expr! {bool_not_converts_0_to_1, "not 0", 1}
 test_return_expr! {bit_not, "~345", -346}
 test_return_expr! {unary_neg, "-(2 + 3)", -5}
-//test_return_expr!{unary_plus, "+(2 + 3)", 5}
+test_return_expr! {unary_plus, "+(2 + 3)", 5}
 
 #[test]
 fn reports_static_analysis_failure_for_call_to_unknown_function() {
@@ -393,18 +782,1113 @@ function main() {
 }
     "#;
     let file = FileHandle::dummy();
-    let prog = parse_Programme(Matcher::new(file, text)).unwrap();
+    let prog = parse_script(file, text);
     let mut output = Vec::new();
     let mut input: &'static [u8] = &[];
     {
-        let err = exec(&prog, Vec::new(), &mut input, &mut output).unwrap_err();
+        let err = exec(
+            &prog,
+            &Options::default(),
+            Vec::new(),
+            &mut input,
+            &mut output,
+            &mut |_| {},
+        )
+        .unwrap_err();
         assert_eq!(
             err,
             ExecError::StaticAnalysisFailed(vec![StaticAnalysisError::CallUnknownFunction(
                 "unk".to_string(),
+                None,
                 Location::new(file, 3, 4, 23),
                 Location::new(file, 3, 13, 32),
             )])
         );
     };
 }
+
+#[test]
+fn check_analyses_the_parts_of_functions_that_parsed() {
+    let text = r#"
+function f() {
+    let a = 1 2;
+    unk1(a);
+}
+function main() {
+    f();
+    unk2();
+}
+    "#;
+    let file = FileHandle::dummy();
+    let mut errors = Vec::new();
+    let prog = parse_Programme(&mut errors, Matcher::new(file, text)).unwrap();
+    assert_eq!(errors.len(), 1);
+    // The declaration of `a` didn't parse, so its use isn't reported
+    assert_eq!(
+        check(&script(file, prog), &Options::default(), &mut |_| {}).unwrap_err(),
+        ExecError::StaticAnalysisFailed(vec![
+            StaticAnalysisError::CallUnknownFunction(
+                "unk1".to_string(),
+                None,
+                Location::new(file, 4, 4, 37),
+                Location::new(file, 4, 11, 44),
+            ),
+            StaticAnalysisError::CallUnknownFunction(
+                "unk2".to_string(),
+                None,
+                Location::new(file, 8, 4, 79),
+                Location::new(file, 8, 10, 85),
+            ),
+        ])
+    );
+    // Recovering from the missing `;` skips the declaration of `y`
+    let text = "function main() {\n    let x = 1\n    let y = 2;\n    y += x;\n}";
+    errors.clear();
+    let prog = parse_Programme(&mut errors, Matcher::new(file, text)).unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(
+        check(&script(file, prog), &Options::default(), &mut |_| {}),
+        Ok(())
+    );
+    // The assignment that didn't parse could have made `a` an array
+    let text = "function main() { let a = 0; a = [1] 2; return a[0]; }";
+    let prog = parse_Programme(&mut Vec::new(), Matcher::new(file, text)).unwrap();
+    let options = Options {
+        infer_types: true,
+        ..Options::default()
+    };
+    assert_eq!(check(&script(file, prog), &options, &mut |_| {}), Ok(()));
+}
+
+#[test]
+fn reports_static_analysis_failure_for_break_and_continue_outside_loop() {
+    let text = r#"
+function main() {
+    if 1 { break; }
+    continue;
+}
+    "#;
+    let file = FileHandle::dummy();
+    let prog = parse_script(file, text);
+    assert_eq!(
+        check(&prog, &Options::default(), &mut |_| {}).unwrap_err(),
+        ExecError::StaticAnalysisFailed(vec![
+            StaticAnalysisError::BreakOutsideLoop(
+                Location::new(file, 3, 11, 30),
+                Location::new(file, 3, 16, 35),
+            ),
+            StaticAnalysisError::ContinueOutsideLoop(
+                Location::new(file, 4, 4, 43),
+                Location::new(file, 4, 12, 51),
+            ),
+        ])
+    );
+}
+
+#[test]
+fn reports_static_analysis_failure_for_global_read_before_set() {
+    let text = r#"
+const A = B + 1;
+const B = 2;
+function main() {
+}
+    "#;
+    let file = FileHandle::dummy();
+    let prog = parse_script(file, text);
+    assert_eq!(
+        check(&prog, &Options::default(), &mut |_| {}).unwrap_err(),
+        ExecError::StaticAnalysisFailed(vec![StaticAnalysisError::GlobalReadBeforeInitialised(
+            "B".to_string(),
+            Location::new(file, 2, 10, 11),
+            Location::new(file, 2, 11, 12),
+        ),])
+    );
+}
+
+#[test]
+fn reports_static_analysis_failure_for_call_that_reads_global_before_set() {
+    let text = r#"
+const A = f();
+const B = [1, 2];
+function f() { return g(); }
+function g() { let A = 0; return A + B[1]; }
+function main() {
+}
+    "#;
+    let file = FileHandle::dummy();
+    let prog = parse_script(file, text);
+    assert_eq!(
+        check(&prog, &Options::default(), &mut |_| {}).unwrap_err(),
+        ExecError::StaticAnalysisFailed(vec![
+            StaticAnalysisError::CallReadsGlobalBeforeInitialised(
+                "f".to_string(),
+                "B".to_string(),
+                Location::new(file, 2, 10, 11),
+                Location::new(file, 2, 13, 14),
+            ),
+        ])
+    );
+}
+
+#[test]
+fn reports_static_analysis_failure_for_duplicate_global() {
+    let text = r#"
+global A = 1;
+global A = 2;
+const B = A;
+function main() {
+}
+    "#;
+    let file = FileHandle::dummy();
+    let prog = parse_script(file, text);
+    assert_eq!(
+        check(&prog, &Options::default(), &mut |_| {}).unwrap_err(),
+        ExecError::StaticAnalysisFailed(vec![StaticAnalysisError::DuplicateGlobal(
+            "A".to_string(),
+            Location::new(file, 2, 7, 8),
+            Location::new(file, 2, 8, 9),
+            Location::new(file, 3, 7, 22),
+            Location::new(file, 3, 8, 23),
+        )])
+    );
+}
+
+#[test]
+fn reports_static_analysis_failure_for_assign_to_const() {
+    let text = r#"
+const A = 1;
+function main() {
+    A = 2;
+}
+    "#;
+    let file = FileHandle::dummy();
+    let prog = parse_script(file, text);
+    assert_eq!(
+        check(&prog, &Options::default(), &mut |_| {}).unwrap_err(),
+        ExecError::StaticAnalysisFailed(vec![StaticAnalysisError::AssignToConst(
+            "A".to_string(),
+            Location::new(file, 4, 4, 36),
+            Location::new(file, 4, 5, 37),
+        )])
+    );
+}
+
+#[test]
+fn modules_have_their_own_namespace() {
+    let prog = parse_modules(&[
+        (
+            "prelude",
+            &[],
+            "pub function twice(x) { return helper(x) * 2; } function helper(x) { return x; }",
+        ),
+        (
+            "maths",
+            &[],
+            "pub function add(a, b) { return helper(a) + b; } function helper(x) { return x * 10; }",
+        ),
+        (
+            "main",
+            &["maths"],
+            "function helper(x) { return x + 1; }
+            function main() { return maths::add(1, 2) + twice(3) + prelude::twice(4) + helper(5); }",
+        ),
+    ]);
+    let mut output = Vec::new();
+    let mut input: &'static [u8] = &[];
+    let status_code = exec(
+        &prog,
+        &Options::default(),
+        Vec::new(),
+        &mut input,
+        &mut output,
+        &mut |_| {},
+    )
+    .unwrap();
+    assert_eq!(status_code, 12 + 6 + 8 + 6);
+}
+
+#[test]
+fn reports_static_analysis_failure_for_call_to_private_function() {
+    let prog = parse_modules(&[
+        ("prelude", &[], "function helper() {}"),
+        ("maths", &[], "function add(a, b) { return a + b; }"),
+        (
+            "main",
+            &["maths"],
+            "function main() { maths::add(1, 2); helper(); }",
+        ),
+    ]);
+    let file = prog[2].file;
+    assert_eq!(
+        check(&prog, &Options::default(), &mut |_| {}).unwrap_err(),
+        ExecError::StaticAnalysisFailed(vec![
+            StaticAnalysisError::CallPrivateFunction(
+                "maths::add".to_string(),
+                Location::new(file, 1, 18, 18),
+                Location::new(file, 1, 34, 34),
+            ),
+            StaticAnalysisError::CallPrivateFunction(
+                "helper".to_string(),
+                Location::new(file, 1, 36, 36),
+                Location::new(file, 1, 44, 44),
+            ),
+        ])
+    );
+}
+
+#[test]
+fn reports_static_analysis_failure_for_call_to_unimported_module() {
+    let prog = parse_modules(&[
+        ("prelude", &[], ""),
+        ("maths", &[], "pub function add(a, b) { return a + b; }"),
+        ("main", &[], "function main() { maths::add(1, 2); }"),
+    ]);
+    let file = prog[2].file;
+    assert_eq!(
+        check(&prog, &Options::default(), &mut |_| {}).unwrap_err(),
+        ExecError::StaticAnalysisFailed(vec![StaticAnalysisError::UnknownModule(
+            "maths".to_string(),
+            Location::new(file, 1, 18, 18),
+            Location::new(file, 1, 34, 34),
+        )])
+    );
+}
+
+#[test]
+fn reports_static_analysis_failure_for_duplicate_function() {
+    let text = r#"
+function f(a) { return 1; }
+pub function f() { return 2; }
+function main() { return f(0); }
+    "#;
+    let file = FileHandle::dummy();
+    let prog = parse_script(file, text);
+    assert_eq!(
+        check(&prog, &Options::default(), &mut |_| {}).unwrap_err(),
+        ExecError::StaticAnalysisFailed(vec![StaticAnalysisError::DuplicateFunction(
+            "f".to_string(),
+            Location::new(file, 2, 0, 1),
+            Location::new(file, 2, 13, 14),
+            Location::new(file, 3, 0, 29),
+            Location::new(file, 3, 16, 45),
+        )])
+    );
+}
+
+#[test]
+fn script_can_reuse_prelude_function_names() {
+    let prog = parse_modules(&[
+        ("prelude", &[], "pub function print(x) { return 1; }"),
+        (
+            "main",
+            &[],
+            "function print(x) { return 2; } function main() { return print(0); }",
+        ),
+    ]);
+    let mut output = Vec::new();
+    let mut input: &'static [u8] = &[];
+    assert_eq!(
+        exec(
+            &prog,
+            &Options::default(),
+            Vec::new(),
+            &mut input,
+            &mut output,
+            &mut |_| {}
+        ),
+        Ok(2)
+    );
+}
+
+#[test]
+fn reports_static_analysis_failure_for_wrong_argument_count() {
+    let text = r#"
+function f(a, b) {}
+function main() {
+    f(1);
+    f(1, 2, 3);
+    putc();
+}
+    "#;
+    let file = FileHandle::dummy();
+    let prog = parse_script(file, text);
+    assert_eq!(
+        check(&prog, &Options::default(), &mut |_| {}).unwrap_err(),
+        ExecError::StaticAnalysisFailed(vec![
+            StaticAnalysisError::WrongArgumentCount(
+                "f".to_string(),
+                vec!["a".to_string(), "b".to_string()],
+                1,
+                Location::new(file, 4, 4, 43),
+                Location::new(file, 4, 8, 47),
+            ),
+            StaticAnalysisError::WrongArgumentCount(
+                "f".to_string(),
+                vec!["a".to_string(), "b".to_string()],
+                3,
+                Location::new(file, 5, 4, 53),
+                Location::new(file, 5, 14, 63),
+            ),
+            StaticAnalysisError::WrongArgumentCount(
+                "putc".to_string(),
+                vec!["char".to_string()],
+                0,
+                Location::new(file, 6, 4, 69),
+                Location::new(file, 6, 10, 75),
+            ),
+        ])
+    );
+}
+
+#[test]
+fn reports_static_analysis_failure_for_undeclared_variables() {
+    let text = r#"
+function f() {
+    return a;
+}
+function main() {
+    b = 1;
+    b += c;
+}
+    "#;
+    let file = FileHandle::dummy();
+    let prog = parse_script(file, text);
+    assert_eq!(
+        check(&prog, &Options::default(), &mut |_| {}).unwrap_err(),
+        ExecError::StaticAnalysisFailed(vec![
+            StaticAnalysisError::UndeclaredVariable(
+                "a".to_string(),
+                None,
+                Location::new(file, 3, 11, 27),
+                Location::new(file, 3, 12, 28),
+            ),
+            StaticAnalysisError::AssignToUndeclaredVariable(
+                "b".to_string(),
+                None,
+                Location::new(file, 6, 4, 54),
+                Location::new(file, 6, 5, 55),
+            ),
+            StaticAnalysisError::AssignToUndeclaredVariable(
+                "b".to_string(),
+                None,
+                Location::new(file, 7, 4, 65),
+                Location::new(file, 7, 5, 66),
+            ),
+            StaticAnalysisError::UndeclaredVariable(
+                "c".to_string(),
+                None,
+                Location::new(file, 7, 9, 70),
+                Location::new(file, 7, 10, 71),
+            ),
+        ])
+    );
+}
+
+#[test]
+fn block_scoping_and_shadowing() {
+    let result = compile_and_run_programme(
+        "\
+            function main() {
+                let a = 1;
+                let total = 0;
+                if a {
+                    let a = a + 10;
+                    total += a;
+                }
+                for i in 0..3 {
+                    let b = i * 100;
+                    total += b;
+                }
+                let c = 1000;
+                return total + a + c;
+            }
+        ",
+    );
+    assert_eq!(result.status_code, 11 + 300 + 1 + 1000);
+}
+
+#[test]
+fn reports_static_analysis_failure_for_variable_used_after_its_block() {
+    let text = r#"
+function main() {
+    while 1 { let a = 1; }
+    for i in 0..2 {}
+    return a + i;
+}
+    "#;
+    let file = FileHandle::dummy();
+    let prog = parse_script(file, text);
+    assert_eq!(
+        check(&prog, &Options::default(), &mut |_| {}).unwrap_err(),
+        ExecError::StaticAnalysisFailed(vec![
+            StaticAnalysisError::UndeclaredVariable(
+                "a".to_string(),
+                None,
+                Location::new(file, 5, 11, 78),
+                Location::new(file, 5, 12, 79),
+            ),
+            StaticAnalysisError::UndeclaredVariable(
+                "i".to_string(),
+                None,
+                Location::new(file, 5, 15, 82),
+                Location::new(file, 5, 16, 83),
+            ),
+        ])
+    );
+}
+
+#[test]
+fn suggests_close_names_for_unknown_functions_and_variables() {
+    let text = r#"
+function dict_size_set(d, size) {}
+function main() {
+    let count = 1;
+    dict_size_Set(0, Count);
+    lenght(0);
+}
+    "#;
+    let file = FileHandle::dummy();
+    let prog = parse_script(file, text);
+    assert_eq!(
+        check(&prog, &Options::default(), &mut |_| {}).unwrap_err(),
+        ExecError::StaticAnalysisFailed(vec![
+            StaticAnalysisError::UndeclaredVariable(
+                "Count".to_string(),
+                Some("count".to_string()),
+                Location::new(file, 5, 21, 94),
+                Location::new(file, 5, 26, 99),
+            ),
+            StaticAnalysisError::CallUnknownFunction(
+                "dict_size_Set".to_string(),
+                Some("dict_size_set".to_string()),
+                Location::new(file, 5, 4, 77),
+                Location::new(file, 5, 27, 100),
+            ),
+            StaticAnalysisError::CallUnknownFunction(
+                "lenght".to_string(),
+                None,
+                Location::new(file, 6, 4, 106),
+                Location::new(file, 6, 13, 115),
+            ),
+        ])
+    );
+}
+
+#[test]
+fn type_inference_reports_operations_that_always_fail() {
+    let text = r#"
+function make() { return [1, 2]; }
+function main() {
+    let a = make();
+    let b = a + 1;
+    let n = 5;
+    n[0] = 1;
+    let c = new([1]);
+    return [1][[0]];
+}
+    "#;
+    let file = FileHandle::dummy();
+    let prog = parse_script(file, text);
+    assert_eq!(check(&prog, &Options::default(), &mut |_| {}), Ok(()));
+    let options = Options {
+        infer_types: true,
+        ..Options::default()
+    };
+    assert_eq!(
+        check(&prog, &options, &mut |_| {}).unwrap_err(),
+        ExecError::StaticAnalysisFailed(vec![
+            StaticAnalysisError::ExpectedIntGotArray(
+                Location::new(file, 5, 12, 86),
+                Location::new(file, 5, 13, 87),
+            ),
+            StaticAnalysisError::ExpectedArrayGotInt(
+                Location::new(file, 7, 4, 112),
+                Location::new(file, 7, 5, 113),
+            ),
+            StaticAnalysisError::ExpectedIntGotArray(
+                Location::new(file, 8, 16, 138),
+                Location::new(file, 8, 19, 141),
+            ),
+            StaticAnalysisError::ExpectedIntGotArray(
+                Location::new(file, 9, 15, 159),
+                Location::new(file, 9, 18, 162),
+            ),
+        ])
+    );
+}
+
+#[test]
+fn type_inference_accepts_values_that_could_be_either() {
+    let text = r#"
+function either(x) {
+    if x { return 1; }
+    return [1];
+}
+function main(args) {
+    let total = either(len(args)) + 1;
+    let arr = 0;
+    while total < 3 {
+        total += 1;
+        arr = [1];
+    }
+    for arg in args {
+        arr = arg;
+    }
+    return arr[0] + args[0][0];
+}
+    "#;
+    let file = FileHandle::dummy();
+    let prog = parse_script(file, text);
+    assert_eq!(
+        check(
+            &prog,
+            &Options {
+                infer_types: true,
+                ..Options::default()
+            },
+            &mut |_| {}
+        ),
+        Ok(())
+    );
+}
+
+#[test]
+fn type_inference_uses_functions_that_shadow_platform_functions() {
+    let text = "function new(x) { return len(x); } function main() { return new([1, 2, 3]); }";
+    let prog = parse_script(FileHandle::dummy(), text);
+    let options = Options {
+        infer_types: true,
+        ..Options::default()
+    };
+    assert_eq!(check(&prog, &options, &mut |_| {}), Ok(()));
+    let mut output = Vec::new();
+    let mut input: &'static [u8] = &[];
+    assert_eq!(
+        exec(
+            &prog,
+            &options,
+            Vec::new(),
+            &mut input,
+            &mut output,
+            &mut |_| {}
+        ),
+        Ok(3)
+    );
+}
+
+#[test]
+fn lints_report_suspicious_code() {
+    let text = r#"
+function unused() { return 1; }
+function helper(x, _y) {
+    return 2;
+    x;
+}
+function main(args) {
+    let a = 1;
+    let _b = 2;
+    let c = 3;
+    c = 4;
+    a + 1;
+    while 1 { helper(a, 0); }
+    return 0;
+}
+    "#;
+    let file = FileHandle::dummy();
+    let prog = parse_script(file, text);
+    assert_eq!(
+        warnings(&prog, &Options::default()),
+        vec![
+            LintWarning::NoEffect(Location::new(file, 5, 4, 76), Location::new(file, 5, 5, 77)),
+            LintWarning::UnreachableCode(
+                Location::new(file, 5, 4, 76),
+                Location::new(file, 5, 5, 77),
+            ),
+            LintWarning::NoEffect(
+                Location::new(file, 12, 4, 164),
+                Location::new(file, 12, 9, 169)
+            ),
+            LintWarning::InfiniteLoop(
+                Location::new(file, 13, 10, 181),
+                Location::new(file, 13, 11, 182),
+            ),
+            LintWarning::UnusedLocal(
+                "c".to_string(),
+                Location::new(file, 10, 8, 142),
+                Location::new(file, 10, 9, 143),
+            ),
+            LintWarning::UnreachableFunction(
+                "unused".to_string(),
+                Location::new(file, 2, 9, 10),
+                Location::new(file, 2, 15, 16),
+            ),
+        ]
+    );
+}
+
+#[test]
+fn lint_levels_decide_whether_lints_warn_or_fail() {
+    let text = r#"
+function helper(x) {
+    return 3;
+}
+function main(args) {
+    let a = 1;
+    return helper(0);
+}
+    "#;
+    let file = FileHandle::dummy();
+    let prog = parse_script(file, text);
+    let mut input = "".as_bytes();
+    let mut output = Vec::new();
+    let mut options = Options::default();
+    let unused_local = || {
+        LintWarning::UnusedLocal(
+            "a".to_string(),
+            Location::new(file, 6, 8, 68),
+            Location::new(file, 6, 9, 69),
+        )
+    };
+    // The entry point's parameter isn't linted
+    assert_eq!(
+        warnings(&prog, &options),
+        vec![
+            LintWarning::UnusedParameter(
+                "x".to_string(),
+                "helper".to_string(),
+                Location::new(file, 2, 16, 17),
+                Location::new(file, 2, 17, 18),
+            ),
+            unused_local(),
+        ]
+    );
+    assert_eq!(
+        exec(
+            &prog,
+            &options,
+            Vec::new(),
+            &mut input,
+            &mut output,
+            &mut |_| {}
+        ),
+        Ok(3)
+    );
+
+    options.lints.set(Lint::UnusedParameter, LintLevel::Allow);
+    options.lints.set(Lint::UnusedLocal, LintLevel::Deny);
+    assert_eq!(warnings(&prog, &options), vec![]);
+    assert_eq!(
+        check(&prog, &options, &mut |_| {}).unwrap_err(),
+        ExecError::StaticAnalysisFailed(vec![StaticAnalysisError::DeniedLint(unused_local())])
+    );
+}
+
+#[test]
+fn reports_static_analysis_failure_for_bad_entry_point() {
+    let file = FileHandle::dummy();
+    let prog = parse_script(file, "function mian() {}");
+    assert_eq!(
+        check(&prog, &Options::default(), &mut |_| {}).unwrap_err(),
+        ExecError::StaticAnalysisFailed(vec![StaticAnalysisError::MissingEntryPoint(
+            "main".to_string(),
+            file
+        )])
+    );
+
+    let prog = parse_script(file, "function main(a, b) {}");
+    assert_eq!(
+        check(&prog, &Options::default(), &mut |_| {}).unwrap_err(),
+        ExecError::StaticAnalysisFailed(vec![StaticAnalysisError::EntryPointParameters(
+            "main".to_string(),
+            2,
+            Location::new(file, 1, 0, 0),
+            Location::new(file, 1, 19, 19),
+        )])
+    );
+}
+
+#[test]
+fn runs_chosen_entry_point_with_or_without_args() {
+    let text = r#"
+function main(args) { return len(args); }
+function start() { return 7; }
+    "#;
+    let prog = parse_script(FileHandle::dummy(), text);
+    let mut input = "".as_bytes();
+    let mut output = Vec::new();
+    let args = vec!["script".to_string(), "arg".to_string()];
+    assert_eq!(
+        exec(
+            &prog,
+            &Options::default(),
+            args.clone(),
+            &mut input,
+            &mut output,
+            &mut |_| {}
+        ),
+        Ok(2)
+    );
+    let options = Options {
+        entry_point: "start".to_string(),
+        ..Options::default()
+    };
+    assert_eq!(
+        exec(&prog, &options, args, &mut input, &mut output, &mut |_| {}),
+        Ok(7)
+    );
+}
+
+#[test]
+fn reports_runtime_failure_for_array_returned_from_entry_point() {
+    let file = FileHandle::dummy();
+    let prog = parse_script(file, "function main() { return [1]; }");
+    let mut input = "".as_bytes();
+    let mut output = Vec::new();
+    assert_eq!(
+        exec(
+            &prog,
+            &Options::default(),
+            Vec::new(),
+            &mut input,
+            &mut output,
+            &mut |_| {}
+        )
+        .unwrap_err(),
+        ExecError::RuntimeFailure(
+            RuntimeFailureKind::EntryPointReturnedArray("main".to_string()),
+            vec![StackFrame {
+                site: CodeSite {
+                    start: Location::new(file, 1, 0, 0),
+                    end: Location::new(file, 1, 15, 15),
+                },
+                function: Some("main()".to_string()),
+            }]
+        )
+    );
+}
+
+#[test]
+fn out_of_bounds_index_is_a_runtime_failure() {
+    let file = FileHandle::dummy();
+    let mut output = Vec::new();
+    let mut input: &'static [u8] = &[];
+    let mut failure = |text: &str, index, frames: &[(usize, usize, &str)]| {
+        let prog = parse_script(file, text);
+        assert_eq!(
+            exec(
+                &prog,
+                &Options::default(),
+                Vec::new(),
+                &mut input,
+                &mut output,
+                &mut |_| {}
+            )
+            .unwrap_err(),
+            runtime_failure_at(
+                RuntimeFailureKind::IndexOutOfBounds { index, len: 2 },
+                frames
+            )
+        );
+    };
+    // Single lines of ascii so that the char and byte offsets match
+    let get = "function get(a, i) { return a[i]; } ";
+    failure(
+        &format!("{}function main() {{ return get([1, 2], 2); }}", get),
+        2,
+        &[(28, 32, "get(a=[1, 2], i=2)"), (61, 75, "main()")],
+    );
+    failure(
+        "function main() { let a = [1, 2]; a[-1] = 0; }",
+        -1,
+        &[(34, 39, "main()")],
+    );
+    failure(
+        "function main() { let a = [1, 2]; a[1] += 1; a[2] += 1; }",
+        2,
+        &[(45, 49, "main()")],
+    );
+}
+
+#[test]
+fn stack_frames_show_functions_and_arguments() {
+    // Single lines of ascii so that the char and byte offsets match
+    assert_eq!(
+        run_line_to_failure(
+            "function f(a, s) { return a[5]; } \
+             function main() { return f([[1], 2, [1, 2, 3]], \"too long!\"); }"
+        ),
+        runtime_failure_at(
+            RuntimeFailureKind::IndexOutOfBounds { index: 5, len: 3 },
+            &[
+                (26, 30, "f(a=[[..1 item], 2, [..3 items]], s=[..9 items])"),
+                (59, 94, "main()"),
+            ]
+        )
+    );
+    // Reassigning a parameter doesn't change the arguments the call is shown with
+    assert_eq!(
+        run_line_to_failure(
+            "function g(a, b) { a = 99; return b[a]; } \
+             function main() { return g(5, [1]); }"
+        ),
+        runtime_failure_at(
+            RuntimeFailureKind::IndexOutOfBounds { index: 99, len: 1 },
+            &[(34, 38, "g(a=5, b=[1])"), (67, 76, "main()")]
+        )
+    );
+    // The arguments are evaluated before the call, so their failures are in the caller
+    assert_eq!(
+        run_line_to_failure("function f(a) { return a; } function main() { return f(1 / 0); }"),
+        runtime_failure_at(RuntimeFailureKind::DivisionByZero, &[(55, 60, "main()")])
+    );
+}
+
+#[test]
+fn division_by_zero_is_a_runtime_failure() {
+    let file = FileHandle::dummy();
+    let mut output = Vec::new();
+    let mut input: &'static [u8] = &[];
+    // Single lines of ascii so that the char and byte offsets match
+    for &(text, start, end) in &[
+        ("function main() { return 1 / (1 - 1); }", 25, 36),
+        ("function main() { let a = 5; a %= 0; }", 29, 35),
+    ] {
+        let prog = parse_script(file, text);
+        assert_eq!(
+            exec(
+                &prog,
+                &Options::default(),
+                Vec::new(),
+                &mut input,
+                &mut output,
+                &mut |_| {}
+            )
+            .unwrap_err(),
+            ExecError::RuntimeFailure(
+                RuntimeFailureKind::DivisionByZero,
+                vec![StackFrame {
+                    site: CodeSite {
+                        start: Location::new(file, 1, start, start),
+                        end: Location::new(file, 1, end, end),
+                    },
+                    function: Some("main()".to_string()),
+                }]
+            )
+        );
+    }
+}
+
+#[test]
+fn checked_arithmetic_reports_overflow() {
+    let file = FileHandle::dummy();
+    let mut output = Vec::new();
+    let mut input: &'static [u8] = &[];
+    let options = Options {
+        checked_arithmetic: true,
+        ..Options::default()
+    };
+    let prog = parse_script(
+        file,
+        "function main() { return 2147483646 + 1 - -2147483648; }",
+    );
+    assert_eq!(
+        exec(
+            &prog,
+            &Options::default(),
+            Vec::new(),
+            &mut input,
+            &mut output,
+            &mut |_| {}
+        ),
+        Ok(-1)
+    );
+    for text in &[
+        "function main() { return 2147483646 + 1 - -2147483648; }",
+        "function main() { let a = 1; a <<= 32; }",
+        "function main() { return -2147483648 % -1; }",
+        "function main() { let x = -2147483648; return -x; }",
+        "function main() { return - -2147483648; }",
+    ] {
+        let prog = parse_script(file, text);
+        match exec(
+            &prog,
+            &options,
+            Vec::new(),
+            &mut input,
+            &mut output,
+            &mut |_| {},
+        )
+        .unwrap_err()
+        {
+            ExecError::RuntimeFailure(RuntimeFailureKind::Overflow, _) => {}
+            err => panic!("Unexpected error {:?}", err),
+        }
+    }
+    let prog = parse_script(file, "function main() { return 2147483646 + 1; }");
+    assert_eq!(
+        exec(
+            &prog,
+            &options,
+            Vec::new(),
+            &mut input,
+            &mut output,
+            &mut |_| {}
+        ),
+        Ok(2147483647)
+    );
+    let prog = parse_script(file, "function main() { return -2147483648; }");
+    assert_eq!(
+        exec(
+            &prog,
+            &options,
+            Vec::new(),
+            &mut input,
+            &mut output,
+            &mut |_| {}
+        ),
+        Ok(i32::MIN)
+    );
+}
+
+#[test]
+fn test_of_array_is_a_runtime_failure() {
+    assert_eq!(
+        run_line_to_failure("function main() { if [1] {} }"),
+        runtime_failure_at(
+            RuntimeFailureKind::ExpectedIntGotArray,
+            &[(21, 24, "main()")]
+        )
+    );
+    assert_eq!(
+        run_line_to_failure("function main() { return +[1]; }"),
+        runtime_failure_at(
+            RuntimeFailureKind::ExpectedIntGotArray,
+            &[(26, 29, "main()")]
+        )
+    );
+}
+
+#[test]
+fn subscripting_an_int_is_a_runtime_failure() {
+    assert_eq!(
+        run_line_to_failure("function main() { let a = 5; a[0] = a[0]; }"),
+        runtime_failure_at(
+            RuntimeFailureKind::ExpectedArrayGotInt,
+            &[(36, 37, "main()")]
+        )
+    );
+    assert_eq!(
+        run_line_to_failure("function main() { let a = 5; a[0] += 1; }"),
+        runtime_failure_at(
+            RuntimeFailureKind::ExpectedArrayGotInt,
+            &[(29, 30, "main()")]
+        )
+    );
+}
+
+#[test]
+fn platform_function_given_array_is_a_runtime_failure() {
+    for &(function, parameter, end) in &[
+        ("new", "size", 26),
+        ("putc", "char", 27),
+        ("exit", "status", 27),
+    ] {
+        assert_eq!(
+            run_line_to_failure(&format!("function main() {{ {}([1]); }}", function)),
+            runtime_failure_at(
+                RuntimeFailureKind::ArgumentExpectedInt {
+                    function,
+                    parameter
+                },
+                &[(18, end, "main()")]
+            )
+        );
+    }
+}
+
+#[test]
+fn new_with_negative_size_is_a_runtime_failure() {
+    assert_eq!(
+        run_line_to_failure("function main() { return new(0 - 2); }"),
+        runtime_failure_at(
+            RuntimeFailureKind::NegativeArraySize(-2),
+            &[(25, 35, "main()")]
+        )
+    );
+}
+
+#[test]
+fn reports_static_analysis_failure_for_invalid_assignment_target() {
+    let file = FileHandle::dummy();
+    let prog = parse_script(file, "function main() { len([]) = 1; 2 += 1; }");
+    assert_eq!(
+        check(&prog, &Options::default(), &mut |_| {}).unwrap_err(),
+        ExecError::StaticAnalysisFailed(vec![
+            StaticAnalysisError::InvalidAssignmentTarget(
+                Location::new(file, 1, 18, 18),
+                Location::new(file, 1, 25, 25),
+            ),
+            StaticAnalysisError::InvalidAssignmentTarget(
+                Location::new(file, 1, 31, 31),
+                Location::new(file, 1, 32, 32),
+            ),
+        ])
+    );
+}
+
+/// Fails every read and write
+struct BrokenPipe;
+
+impl io::Read for BrokenPipe {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::BrokenPipe.into())
+    }
+}
+
+impl io::Write for BrokenPipe {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::BrokenPipe.into())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Err(io::ErrorKind::BrokenPipe.into())
+    }
+}
+
+#[test]
+fn io_errors_are_runtime_failures() {
+    let text = "function f() { return getc(); } function main() { return f() + putc(1); }";
+    let prog = parse_script(FileHandle::dummy(), text);
+    let mut output = Vec::new();
+    assert_eq!(
+        exec(
+            &prog,
+            &Options::default(),
+            Vec::new(),
+            &mut BrokenPipe,
+            &mut output,
+            &mut |_| {}
+        )
+        .unwrap_err(),
+        runtime_failure_at(
+            RuntimeFailureKind::ReadFailed(io::ErrorKind::BrokenPipe),
+            &[(22, 28, "f()"), (57, 60, "main()")]
+        )
+    );
+    let mut input: &'static [u8] = b"a";
+    assert_eq!(
+        exec(
+            &prog,
+            &Options::default(),
+            Vec::new(),
+            &mut input,
+            &mut BrokenPipe,
+            &mut |_| {}
+        )
+        .unwrap_err(),
+        runtime_failure_at(
+            RuntimeFailureKind::WriteFailed(io::ErrorKind::BrokenPipe),
+            &[(63, 70, "main()")]
+        )
+    );
+}
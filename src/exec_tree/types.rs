@@ -0,0 +1,390 @@
+use super::base::*;
+use super::error::{StaticAnalysisError, StaticAnalysisErrors};
+use std::collections::HashMap;
+
+/// What's known about a value at some point. `Never` is for values that can't be produced, such as
+/// the result of a function that never returns, and `Unknown` for values that could be either.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Type {
+    Never,
+    Int,
+    Array,
+    Unknown,
+}
+
+impl Type {
+    fn join(self, other: Type) -> Type {
+        match (self, other) {
+            (Type::Never, t) | (t, Type::Never) => t,
+            (a, b) if a == b => a,
+            _ => Type::Unknown,
+        }
+    }
+}
+
+/// The types of the local variables in scope. Globals aren't tracked as any function could change
+/// them.
+#[derive(Clone, PartialEq)]
+struct Env<'a> {
+    scopes: Vec<HashMap<&'a str, Type>>,
+}
+
+impl<'a> Env<'a> {
+    fn new() -> Self {
+        Env {
+            scopes: vec![HashMap::new()],
+        }
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &'a str, ty: Type) {
+        self.scopes.last_mut().unwrap().insert(name, ty);
+    }
+
+    fn lookup(&self, name: &str) -> Type {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).cloned())
+            .unwrap_or(Type::Unknown)
+    }
+
+    fn set(&mut self, name: &str, ty: Type) {
+        if let Some(var) = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+        {
+            *var = ty;
+        }
+    }
+
+    /// For when any variable may have been assigned anything
+    fn forget(&mut self) {
+        for ty in self.scopes.iter_mut().flat_map(HashMap::values_mut) {
+            *ty = Type::Unknown;
+        }
+    }
+
+    /// Both must come from the same point in the same block so they have the same variables
+    fn join(mut self, other: &Env<'a>) -> Env<'a> {
+        for (scope, other_scope) in self.scopes.iter_mut().zip(&other.scopes) {
+            for (name, ty) in scope.iter_mut() {
+                *ty = ty.join(other_scope.get(name).cloned().unwrap_or(Type::Unknown));
+            }
+        }
+        self
+    }
+}
+
+/// `None` is used for the state after a statement that never completes, such as a `return`
+fn join_states<'a>(a: Option<Env<'a>>, b: Option<Env<'a>>) -> Option<Env<'a>> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.join(&b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// The states that leave the loop body early
+#[derive(Default)]
+struct LoopExits<'a> {
+    breaks: Option<Env<'a>>,
+    continues: Option<Env<'a>>,
+}
+
+/// The return type of each function, along with the functions that only take ints
+struct Summaries {
+    returns: HashMap<FunctionId, Type>,
+    int_only: Vec<FunctionId>,
+}
+
+impl Summaries {
+    fn new(globals: &Globals) -> Self {
+        let mut summaries = Summaries {
+            returns: HashMap::new(),
+            int_only: Vec::new(),
+        };
+        let platform_funcs = [
+            ("new", Type::Array, true),
+            ("len", Type::Int, false),
+            ("getc", Type::Int, false),
+            ("putc", Type::Int, true),
+            ("exit", Type::Int, true),
+        ];
+        for &(name, ty, int_only) in &platform_funcs {
+            if let Some(id) = globals.platform_func(name) {
+                summaries.returns.insert(id, ty);
+                if int_only {
+                    summaries.int_only.push(id);
+                }
+            }
+        }
+        summaries
+    }
+
+    fn return_type(&self, id: FunctionId) -> Type {
+        self.returns.get(&id).cloned().unwrap_or(Type::Never)
+    }
+}
+
+struct Analyser<'a, 'g, 'io: 'g> {
+    globals: &'g Globals<'io>,
+    summaries: &'g Summaries,
+    returns: Type,
+    loops: Vec<LoopExits<'a>>,
+    failures: StaticAnalysisErrors,
+}
+
+impl<'a, 'g, 'io> Analyser<'a, 'g, 'io> {
+    fn expect_int(&mut self, ty: Type, expr: &ast::Expr) {
+        if ty == Type::Array {
+            self.failures.push(StaticAnalysisError::ExpectedIntGotArray(
+                expr.start, expr.end,
+            ));
+        }
+    }
+
+    fn expect_array(&mut self, ty: Type, expr: &ast::Expr) {
+        if ty == Type::Int {
+            self.failures.push(StaticAnalysisError::ExpectedArrayGotInt(
+                expr.start, expr.end,
+            ));
+        }
+    }
+
+    fn int_expr(&mut self, env: &Env<'a>, expr: &'a ast::Expr) {
+        let ty = self.expr(env, expr);
+        self.expect_int(ty, expr);
+    }
+
+    fn expr(&mut self, env: &Env<'a>, expr: &'a ast::Expr) -> Type {
+        use ast::ExprKind::*;
+        match expr.kind {
+            Number(_) | Char(_) => Type::Int,
+            String(_) => Type::Array,
+            Array(ref exprs) => {
+                for expr in exprs {
+                    self.expr(env, expr);
+                }
+                Type::Array
+            }
+            Identifier(name) => env.lookup(name),
+            BinaryOp(ref lhs, _, ref rhs) => {
+                self.int_expr(env, lhs);
+                self.int_expr(env, rhs);
+                Type::Int
+            }
+            UnaryOp(_, ref expr) => {
+                self.int_expr(env, expr);
+                Type::Int
+            }
+            Call(module, name, ref args) => {
+                let arg_types = args
+                    .iter()
+                    .map(|arg| self.expr(env, arg))
+                    .collect::<Vec<_>>();
+                match self.globals.reference_func(module, name) {
+                    Ok((id, _)) => {
+                        if self.summaries.int_only.contains(&id) {
+                            for (&ty, arg) in arg_types.iter().zip(args) {
+                                self.expect_int(ty, arg);
+                            }
+                        }
+                        self.summaries.return_type(id)
+                    }
+                    Err(_) => Type::Unknown,
+                }
+            }
+            Subscription(ref array, ref index) => {
+                let ty = self.expr(env, array);
+                self.expect_array(ty, array);
+                self.int_expr(env, index);
+                Type::Unknown
+            }
+            Error => Type::Unknown,
+        }
+    }
+
+    fn block(&mut self, mut env: Env<'a>, stmts: &'a [ast::Statement]) -> Option<Env<'a>> {
+        env.push_scope();
+        let mut state = Some(env);
+        for stmt in stmts {
+            // Nothing after a statement that never completes can run, so nothing can fail
+            state = match state {
+                Some(env) => self.stmt(env, stmt),
+                None => break,
+            };
+        }
+        state.map(|mut env| {
+            env.pop_scope();
+            env
+        })
+    }
+
+    /// Runs the body until the state at the top of the loop stops changing, so that only the
+    /// failures found with the final state are kept
+    fn analyse_loop(
+        &mut self,
+        entry: Env<'a>,
+        test: Option<&'a ast::Expr>,
+        stmts: &'a [ast::Statement],
+    ) -> Env<'a> {
+        let mut head = entry.clone();
+        loop {
+            let failures_before = self.failures.len();
+            if let Some(test) = test {
+                self.int_expr(&head, test);
+            }
+            self.loops.push(LoopExits::default());
+            let end = self.block(head.clone(), stmts);
+            let exits = self.loops.pop().unwrap();
+            let next_head = join_states(Some(entry.clone()), join_states(end, exits.continues))
+                .expect("The loop can be entered");
+            if next_head == head {
+                return join_states(Some(head), exits.breaks).unwrap();
+            }
+            self.failures.truncate(failures_before);
+            head = next_head;
+        }
+    }
+
+    fn stmt(&mut self, mut env: Env<'a>, stmt: &'a ast::Statement) -> Option<Env<'a>> {
+        use ast::ExprKind;
+        use ast::Statement::*;
+        match *stmt {
+            // A statement that didn't parse could have assigned or returned anything
+            Expr(ref expr) if expr.is_error() => {
+                env.forget();
+                self.returns = self.returns.join(Type::Unknown);
+            }
+            Expr(ref expr) => {
+                self.expr(&env, expr);
+            }
+            Return(ref expr) => {
+                let ty = self.expr(&env, expr);
+                self.returns = self.returns.join(ty);
+                return None;
+            }
+            Assign(ref lexpr, ref rexpr) => {
+                let ty = self.expr(&env, rexpr);
+                match lexpr.kind {
+                    ExprKind::Identifier(name) => env.set(name, ty),
+                    _ => {
+                        self.expr(&env, lexpr);
+                    }
+                }
+            }
+            CompoundAssign(ref lexpr, _, ref rexpr) => {
+                self.int_expr(&env, rexpr);
+                self.int_expr(&env, lexpr);
+                if let ExprKind::Identifier(name) = lexpr.kind {
+                    env.set(name, Type::Int);
+                }
+            }
+            Declare(_, name, _, ref expr) => {
+                let ty = self.expr(&env, expr);
+                env.declare(name, ty);
+            }
+            If(ref test, ref stmts, ref else_stmts) => {
+                self.int_expr(&env, test);
+                let then_state = self.block(env.clone(), stmts);
+                return join_states(then_state, self.block(env, else_stmts));
+            }
+            While(ref test, ref stmts) => env = self.analyse_loop(env, Some(test), stmts),
+            ForRange(name, ref start, ref end, ref stmts) => {
+                self.int_expr(&env, start);
+                self.int_expr(&env, end);
+                env.push_scope();
+                env.declare(name, Type::Int);
+                env = self.analyse_loop(env, None, stmts);
+                env.pop_scope();
+            }
+            ForEach(name, ref array, ref stmts) => {
+                let ty = self.expr(&env, array);
+                self.expect_array(ty, array);
+                env.push_scope();
+                env.declare(name, Type::Unknown);
+                env = self.analyse_loop(env, None, stmts);
+                env.pop_scope();
+            }
+            Break(..) => {
+                if let Some(exits) = self.loops.last_mut() {
+                    exits.breaks = join_states(exits.breaks.take(), Some(env));
+                }
+                return None;
+            }
+            Continue(..) => {
+                if let Some(exits) = self.loops.last_mut() {
+                    exits.continues = join_states(exits.continues.take(), Some(env));
+                }
+                return None;
+            }
+        }
+        Some(env)
+    }
+}
+
+/// Gives the function's return type and the operations in it that are certain to fail
+fn analyse_function(
+    globals: &Globals,
+    summaries: &Summaries,
+    func: &ast::Function,
+) -> (Type, StaticAnalysisErrors) {
+    let mut analyser = Analyser {
+        globals,
+        summaries,
+        returns: Type::Never,
+        loops: Vec::new(),
+        failures: StaticAnalysisErrors::new(),
+    };
+    let mut env = Env::new();
+    for arg in &func.arguments {
+        env.declare(arg, Type::Unknown);
+    }
+    if analyser.block(env, &func.stmts).is_some() {
+        // Falling off the end returns 0
+        analyser.returns = analyser.returns.join(Type::Int);
+    }
+    (analyser.returns, analyser.failures)
+}
+
+/// Infer whether values are ints or arrays, reporting operations that can only fail. The return
+/// type of every function is found first, so that calls can be followed.
+pub fn infer_types(globals: &mut Globals, programme: &[ast::Module]) -> StaticAnalysisErrors {
+    let mut summaries = Summaries::new(globals);
+    loop {
+        let mut changed = false;
+        for module in programme {
+            globals.enter_module(module.file);
+            for (id, func) in module_functions(globals, module) {
+                // Joined so that duplicate definitions sharing an id can't keep swapping it
+                let old = summaries.return_type(id);
+                let ty = old.join(analyse_function(globals, &summaries, func).0);
+                if old != ty {
+                    summaries.returns.insert(id, ty);
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    let mut failures = StaticAnalysisErrors::new();
+    for module in programme {
+        globals.enter_module(module.file);
+        for (_, func) in module_functions(globals, module) {
+            failures.extend(analyse_function(globals, &summaries, func).1);
+        }
+    }
+    failures
+}
@@ -0,0 +1,164 @@
+use super::base::ast;
+use lexer::Location;
+use std::collections::HashMap;
+
+/// A variable read by an expression. Names that no local in scope has may be globals.
+#[derive(Copy, Clone, Debug)]
+pub enum Variable<'a> {
+    /// Numbered in the order the locals were declared
+    Local(usize),
+    Global(&'a str),
+}
+
+/// How a local was declared, located at its name
+#[derive(Copy, Clone, Debug)]
+pub enum Declaration {
+    Parameter(Location, Location),
+    Let(Location, Location),
+    LoopVariable,
+}
+
+/// What a pass over the AST is told about as the `Walker` finds it
+pub trait Visitor<'a> {
+    fn declare(&mut self, _name: &'a str, _declaration: Declaration) {}
+
+    fn read(&mut self, _variable: Variable<'a>) {}
+
+    /// Assigning to a variable doesn't read it, but a compound assignment does unless it's
+    /// overridden
+    fn compound_assign(&mut self, variable: Variable<'a>) {
+        self.read(variable);
+    }
+
+    fn call(&mut self, _module: Option<&'a str>, _name: &'a str, _call: &'a ast::Expr) {}
+
+    /// Before the statement is walked
+    fn stmt(&mut self, _stmt: &'a ast::Statement) {}
+
+    /// After the block is walked
+    fn end_block(&mut self, _stmts: &'a [ast::Statement]) {}
+}
+
+/// Walks expressions and statements keeping track of which locals are in scope, so that the
+/// visitor is told which variable each name refers to
+pub struct Walker<'a, V> {
+    pub visitor: V,
+    scopes: Vec<HashMap<&'a str, usize>>,
+    locals: usize,
+}
+
+impl<'a, V: Visitor<'a>> Walker<'a, V> {
+    pub fn new(visitor: V) -> Self {
+        Walker {
+            visitor,
+            scopes: vec![HashMap::new()],
+            locals: 0,
+        }
+    }
+
+    /// Declare a local in the current scope
+    pub fn declare(&mut self, name: &'a str, declaration: Declaration) {
+        self.scopes.last_mut().unwrap().insert(name, self.locals);
+        self.locals += 1;
+        self.visitor.declare(name, declaration);
+    }
+
+    fn variable(&self, name: &'a str) -> Variable<'a> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).cloned())
+            .map_or(Variable::Global(name), Variable::Local)
+    }
+
+    pub fn expr(&mut self, expr: &'a ast::Expr) {
+        use ast::ExprKind::*;
+        match expr.kind {
+            Number(_) | Char(_) | String(_) | Error => {}
+            Array(ref exprs) => {
+                for expr in exprs {
+                    self.expr(expr);
+                }
+            }
+            BinaryOp(ref lhs, _, ref rhs) | Subscription(ref lhs, ref rhs) => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            UnaryOp(_, ref expr) => self.expr(expr),
+            Call(module, name, ref args) => {
+                self.visitor.call(module, name, expr);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            Identifier(name) => {
+                let variable = self.variable(name);
+                self.visitor.read(variable);
+            }
+        }
+    }
+
+    pub fn block(&mut self, stmts: &'a [ast::Statement]) {
+        self.scopes.push(HashMap::new());
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+        self.scopes.pop();
+        self.visitor.end_block(stmts);
+    }
+
+    fn loop_block(&mut self, name: &'a str, stmts: &'a [ast::Statement]) {
+        self.scopes.push(HashMap::new());
+        self.declare(name, Declaration::LoopVariable);
+        self.block(stmts);
+        self.scopes.pop();
+    }
+
+    fn stmt(&mut self, stmt: &'a ast::Statement) {
+        use ast::Statement::*;
+        self.visitor.stmt(stmt);
+        match *stmt {
+            Expr(ref expr) | Return(ref expr) => self.expr(expr),
+            // Assigning to a variable doesn't read it, but assigning into an array does
+            Assign(ref target, ref expr) => {
+                self.expr(expr);
+                if let ast::ExprKind::Identifier(_) = target.kind {
+                    return;
+                }
+                self.expr(target);
+            }
+            CompoundAssign(ref target, _, ref expr) => {
+                self.expr(expr);
+                if let ast::ExprKind::Identifier(name) = target.kind {
+                    let variable = self.variable(name);
+                    self.visitor.compound_assign(variable);
+                    return;
+                }
+                self.expr(target);
+            }
+            Declare(start, name, end, ref expr) => {
+                self.expr(expr);
+                self.declare(name, Declaration::Let(start, end));
+            }
+            If(ref test, ref block, ref else_block) => {
+                self.expr(test);
+                self.block(block);
+                self.block(else_block);
+            }
+            While(ref test, ref block) => {
+                self.expr(test);
+                self.block(block);
+            }
+            ForRange(name, ref start, ref end, ref block) => {
+                self.expr(start);
+                self.expr(end);
+                self.loop_block(name, block);
+            }
+            ForEach(name, ref array, ref block) => {
+                self.expr(array);
+                self.loop_block(name, block);
+            }
+            Break(..) | Continue(..) => {}
+        }
+    }
+}
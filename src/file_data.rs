@@ -2,7 +2,7 @@ use error::{OuterError, OuterResult};
 use std::io::Read;
 use std::{fs, io};
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct FileHandle(u32);
 
 #[cfg(test)]
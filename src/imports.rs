@@ -0,0 +1,372 @@
+use error::OuterResult;
+use file_data::{FileData, FileHandle};
+use lexer::{Location, Matcher, Tok};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, PartialEq)]
+pub enum ImportError {
+    /// The path imported and the paths that were tried
+    NotFound(String, Vec<String>, Location, Location),
+    /// The path imported leads back to a file that's still being loaded
+    Cycle(String, Location, Location),
+    /// The path imported gives a module with the same name as an earlier import of the file
+    NameClash(String, Location, Location),
+}
+
+#[derive(Debug, PartialEq)]
+struct Import {
+    path: String,
+    start: Location,
+    end: Location,
+}
+
+/// Imports must come before anything else in a file. So they can be found by lexing the start of
+/// the file, which lets every file be loaded before any of them are parsed.
+fn scan_imports(file: FileHandle, text: &str) -> Vec<Import> {
+    let mut lexer = Matcher::new(file, text);
+    let mut imports = Vec::new();
+    loop {
+        match (lexer.next(), lexer.next(), lexer.next()) {
+            (
+                Some(Ok((start, Tok::Import, _))),
+                Some(Ok((_, Tok::String(path), end))),
+                Some(Ok((_, Tok::SemiColon, _))),
+            ) => imports.push(Import {
+                path: path.into_owned(),
+                start,
+                end,
+            }),
+            // The end of the imports, any errors are left for the parser to report
+            _ => return imports,
+        }
+    }
+}
+
+/// A file along with the files it imports
+pub struct LoadedFile {
+    pub handle: FileHandle,
+    pub imports: Vec<FileHandle>,
+}
+
+pub struct LoadedFiles {
+    /// Each file comes after everything it imports
+    pub files: Vec<LoadedFile>,
+    /// The files that were asked for, in the order they were given
+    pub roots: Vec<FileHandle>,
+    pub errors: Vec<ImportError>,
+}
+
+#[derive(Copy, Clone)]
+enum LoadState {
+    Loading,
+    Loaded(FileHandle),
+}
+
+struct Loader<'a> {
+    files: &'a mut FileData,
+    search_path: &'a [String],
+    states: HashMap<PathBuf, LoadState>,
+    order: Vec<LoadedFile>,
+    errors: Vec<ImportError>,
+}
+
+fn canonical_path(path: &str) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path))
+}
+
+/// A module is named after its file without the extension, so `lib/array.sl` is `array`
+pub fn module_name(path: &str) -> String {
+    Path::new(path).file_stem().map_or_else(
+        || path.to_string(),
+        |stem| stem.to_string_lossy().into_owned(),
+    )
+}
+
+impl<'a> Loader<'a> {
+    fn load(&mut self, path: String) -> OuterResult<FileHandle> {
+        let canonical = canonical_path(&path);
+        if let Some(&LoadState::Loaded(handle)) = self.states.get(&canonical) {
+            return Ok(handle);
+        }
+        let handle = self.files.read(path)?;
+        self.states.insert(canonical.clone(), LoadState::Loading);
+        let mut imports = Vec::new();
+        let mut names = Vec::new();
+        for import in scan_imports(handle, self.files.get_contents(handle)) {
+            match self.resolve(handle, &import.path) {
+                Ok(path) => {
+                    let name = module_name(&path);
+                    let imported = match self.states.get(&canonical_path(&path)).cloned() {
+                        Some(LoadState::Loading) => {
+                            self.errors.push(ImportError::Cycle(
+                                import.path,
+                                import.start,
+                                import.end,
+                            ));
+                            continue;
+                        }
+                        Some(LoadState::Loaded(imported)) => imported,
+                        None => self.load(path)?,
+                    };
+                    if imports.contains(&imported) {
+                        continue;
+                    }
+                    if names.contains(&name) {
+                        self.errors.push(ImportError::NameClash(
+                            import.path,
+                            import.start,
+                            import.end,
+                        ));
+                        continue;
+                    }
+                    names.push(name);
+                    imports.push(imported);
+                }
+                Err(tried) => self.errors.push(ImportError::NotFound(
+                    import.path,
+                    tried,
+                    import.start,
+                    import.end,
+                )),
+            }
+        }
+        self.states.insert(canonical, LoadState::Loaded(handle));
+        self.order.push(LoadedFile { handle, imports });
+        Ok(handle)
+    }
+
+    /// Look relative to the importing file and then in each directory of the search path
+    fn resolve(&self, importer: FileHandle, import: &str) -> Result<String, Vec<String>> {
+        let importer_dir = Path::new(self.files.get_name(importer))
+            .parent()
+            .unwrap_or_else(|| Path::new(""));
+        let mut tried = Vec::new();
+        let search_path = self.search_path.iter().map(Path::new);
+        for dir in Some(importer_dir).into_iter().chain(search_path) {
+            let candidate = dir.join(import).to_string_lossy().into_owned();
+            if Path::new(&candidate).is_file() {
+                return Ok(candidate);
+            }
+            tried.push(candidate);
+        }
+        Err(tried)
+    }
+}
+
+/// Read the roots and everything they import. Every file is only read once.
+pub fn load_files(
+    files: &mut FileData,
+    roots: Vec<String>,
+    search_path: &[String],
+) -> OuterResult<LoadedFiles> {
+    let mut loader = Loader {
+        files,
+        search_path,
+        states: HashMap::new(),
+        order: Vec::new(),
+        errors: Vec::new(),
+    };
+    let roots = roots
+        .into_iter()
+        .map(|root| loader.load(root))
+        .collect::<OuterResult<_>>()?;
+    Ok(LoadedFiles {
+        files: loader.order,
+        roots,
+        errors: loader.errors,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::ops::Deref;
+    use std::process;
+
+    /// A directory for a test's files, removed along with them when the test finishes
+    struct TempDir(PathBuf);
+
+    impl Deref for TempDir {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn temp_dir(test_name: &str) -> TempDir {
+        let dir = env::temp_dir().join(format!("starlang-{}-{}", process::id(), test_name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+
+    fn write_file(dir: &Path, name: &str, contents: &str) -> String {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn loaded_names(files: &FileData, loaded: &[LoadedFile], dir: &Path) -> Vec<String> {
+        loaded
+            .iter()
+            .map(|file| {
+                let name = Path::new(files.get_name(file.handle));
+                name.strip_prefix(dir)
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn scan_imports_stops_at_the_first_item() {
+        let file = FileHandle::dummy();
+        let imports = scan_imports(
+            file,
+            "import \"a.sl\";\nimport \"b.sl\";\nfunction f() {}\nimport \"c.sl\";",
+        );
+        assert_eq!(
+            imports,
+            vec![
+                Import {
+                    path: "a.sl".to_string(),
+                    start: Location::new(file, 1, 0, 0),
+                    end: Location::new(file, 1, 13, 13),
+                },
+                Import {
+                    path: "b.sl".to_string(),
+                    start: Location::new(file, 2, 0, 15),
+                    end: Location::new(file, 2, 13, 28),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn loads_imports_before_importers_and_only_once() {
+        let dir = temp_dir("loads_imports_before_importers_and_only_once");
+        let main = write_file(&dir, "main.sl", "import \"a.sl\";\nimport \"lib/b.sl\";\n");
+        write_file(&dir, "a.sl", "import \"lib/c.sl\";\n");
+        write_file(&dir, "lib/b.sl", "import \"c.sl\";\n");
+        write_file(&dir, "lib/c.sl", "");
+        let mut files = FileData::new();
+        let loaded = load_files(&mut files, vec![main], &[]).unwrap();
+        assert_eq!(loaded.errors, vec![]);
+        assert_eq!(
+            loaded_names(&files, &loaded.files, &dir),
+            vec!["lib/c.sl", "a.sl", "lib/b.sl", "main.sl"]
+        );
+    }
+
+    #[test]
+    fn loads_imports_from_the_search_path() {
+        let dir = temp_dir("loads_imports_from_the_search_path");
+        let main = write_file(&dir, "src/main.sl", "import \"util.sl\";\n");
+        write_file(&dir, "lib/util.sl", "");
+        let search_path = [dir.join("lib").to_string_lossy().into_owned()];
+        let mut files = FileData::new();
+        let loaded = load_files(&mut files, vec![main], &search_path).unwrap();
+        assert_eq!(loaded.errors, vec![]);
+        assert_eq!(
+            loaded_names(&files, &loaded.files, &dir),
+            vec!["lib/util.sl", "src/main.sl"]
+        );
+    }
+
+    #[test]
+    fn reports_missing_import() {
+        let dir = temp_dir("reports_missing_import");
+        let main = write_file(&dir, "main.sl", "\nimport \"missing.sl\";\n");
+        let search_path = [dir.join("lib").to_string_lossy().into_owned()];
+        let mut files = FileData::new();
+        let loaded = load_files(&mut files, vec![main], &search_path).unwrap();
+        let file = loaded.files[0].handle;
+        assert_eq!(
+            loaded.errors,
+            vec![ImportError::NotFound(
+                "missing.sl".to_string(),
+                vec![
+                    dir.join("missing.sl").to_string_lossy().into_owned(),
+                    dir.join("lib/missing.sl").to_string_lossy().into_owned(),
+                ],
+                Location::new(file, 2, 0, 1),
+                Location::new(file, 2, 19, 20),
+            )]
+        );
+    }
+
+    #[test]
+    fn reports_import_cycle() {
+        let dir = temp_dir("reports_import_cycle");
+        let main = write_file(&dir, "main.sl", "import \"a.sl\";\n");
+        write_file(&dir, "a.sl", "import \"b.sl\";\n");
+        write_file(&dir, "b.sl", "import \"a.sl\";\n");
+        let mut files = FileData::new();
+        let loaded = load_files(&mut files, vec![main], &[]).unwrap();
+        assert_eq!(
+            loaded_names(&files, &loaded.files, &dir),
+            vec!["b.sl", "a.sl", "main.sl"]
+        );
+        let b = loaded.files[0].handle;
+        assert_eq!(
+            loaded.errors,
+            vec![ImportError::Cycle(
+                "a.sl".to_string(),
+                Location::new(b, 1, 0, 0),
+                Location::new(b, 1, 13, 13),
+            )]
+        );
+    }
+
+    #[test]
+    fn records_the_imports_of_each_file() {
+        let dir = temp_dir("records_the_imports_of_each_file");
+        let main = write_file(&dir, "main.sl", "import \"a.sl\";\nimport \"b.sl\";\n");
+        write_file(&dir, "a.sl", "import \"b.sl\";\n");
+        write_file(&dir, "b.sl", "");
+        let mut files = FileData::new();
+        let loaded = load_files(&mut files, vec![main], &[]).unwrap();
+        assert_eq!(loaded.errors, vec![]);
+        let (b, a, main) = (&loaded.files[0], &loaded.files[1], &loaded.files[2]);
+        assert_eq!(b.imports, vec![]);
+        assert_eq!(a.imports, vec![b.handle]);
+        assert_eq!(main.imports, vec![a.handle, b.handle]);
+        assert_eq!(loaded.roots, vec![main.handle]);
+    }
+
+    #[test]
+    fn reports_module_name_clash() {
+        let dir = temp_dir("reports_module_name_clash");
+        let main = write_file(
+            &dir,
+            "main.sl",
+            "import \"a/util.sl\";\nimport \"b/util.sl\";\n",
+        );
+        write_file(&dir, "a/util.sl", "");
+        write_file(&dir, "b/util.sl", "");
+        let mut files = FileData::new();
+        let loaded = load_files(&mut files, vec![main], &[]).unwrap();
+        let main = loaded.files[2].handle;
+        assert_eq!(loaded.files[2].imports, vec![loaded.files[0].handle]);
+        assert_eq!(
+            loaded.errors,
+            vec![ImportError::NameClash(
+                "b/util.sl".to_string(),
+                Location::new(main, 2, 0, 20),
+                Location::new(main, 2, 18, 38),
+            )]
+        );
+    }
+}
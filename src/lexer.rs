@@ -1,19 +1,31 @@
 use file_data::FileHandle;
+use std::borrow::Cow;
 use std::fmt;
+use std::iter::Peekable;
+use std::str::CharIndices;
 
-#[derive(PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub enum Tok<'input> {
     Identifier(&'input str),
     // Literals
     Integer(i32),
     Char(char),
-    String(&'input str),
+    String(Cow<'input, str>),
     // Key words
+    Import,
+    Pub,
     Function,
     Return,
     Let,
+    Const,
+    Global,
     If,
+    Else,
     While,
+    For,
+    In,
+    Break,
+    Continue,
 
     And,
     Not,
@@ -45,6 +57,19 @@ pub enum Tok<'input> {
     Asterisk,
     Percent,
     ForwardSlash,
+    DotDot,
+    DoubleColon,
+    // Compound assignment
+    PlusEqual,
+    MinusEqual,
+    AsteriskEqual,
+    ForwardSlashEqual,
+    PercentEqual,
+    LeftShiftEqual,
+    RightShiftEqual,
+    AmpersandEqual,
+    PipeEqual,
+    CaretEqual,
 }
 
 #[derive(PartialEq, Debug)]
@@ -57,10 +82,20 @@ pub struct Error {
 pub enum ErrorKind {
     IllegalChar(char),
     LonelyExclamation,
+    LonelyDot,
+    LonelyColon,
     EofInCharLiteral,
     BadCharLiteral,
     EofInString,
+    EolInString,
     MisPlacedCharacterReturn,
+    UnknownEscape(char),
+    MalformedHexEscape,
+    MalformedUnicodeEscape,
+    InvalidUnicodeCodePoint(u32),
+    IntegerLiteralOutOfRange,
+    InvalidDigitInIntegerLiteral(char),
+    IntegerLiteralWithoutDigits,
 }
 
 impl fmt::Display for ErrorKind {
@@ -72,6 +107,14 @@ impl fmt::Display for ErrorKind {
                 f,
                 "Encountered illegal '!' which is only valid as part of '!='"
             ),
+            LonelyDot => write!(
+                f,
+                "Encountered illegal '.' which is only valid as part of '..'"
+            ),
+            LonelyColon => write!(
+                f,
+                "Encountered illegal ':' which is only valid as part of '::'"
+            ),
             EofInCharLiteral => write!(
                 f,
                 "Found end of file whilst looking for end of character literal"
@@ -81,10 +124,40 @@ impl fmt::Display for ErrorKind {
                 f,
                 "Found end of file whilst looking for end of string literal"
             ),
+            EolInString => write!(
+                f,
+                "Found end of line whilst looking for end of string literal, use '\\n' for a \
+                 line break"
+            ),
             MisPlacedCharacterReturn => write!(
                 f,
                 "Character return '\\r' without following line feed '\\n'"
             ),
+            UnknownEscape(c) => write!(f, "Unknown escape sequence '\\{}'", c),
+            MalformedHexEscape => write!(
+                f,
+                "Malformed escape sequence, '\\x' must be followed by two hex digits"
+            ),
+            MalformedUnicodeEscape => write!(
+                f,
+                "Malformed escape sequence, '\\u' must be followed by 1 to 6 hex digits in braces"
+            ),
+            InvalidUnicodeCodePoint(n) => {
+                write!(
+                    f,
+                    "Escape sequence '\\u{{{:x}}}' is not a valid unicode code point",
+                    n
+                )
+            }
+            IntegerLiteralOutOfRange => write!(
+                f,
+                "Integer literal out of range, decimal literals must be from -2147483648 to \
+                 2147483647 and hex, octal or binary literals must fit in 32 bits"
+            ),
+            InvalidDigitInIntegerLiteral(c) => {
+                write!(f, "Invalid digit '{}' in integer literal", c)
+            }
+            IntegerLiteralWithoutDigits => write!(f, "Integer literal has no digits"),
         }
     }
 }
@@ -123,8 +196,62 @@ impl Location {
 //    }
 //}
 
+fn is_identifier_start_char(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
 fn is_identifier_char(c: char) -> bool {
-    matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | '_')
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Decode the escape sequence following the backslash at `start`. Errors are reported as the
+/// byte offset they should be located at, which for end of file is the start of the token.
+fn extract_escape(
+    chars: &mut Peekable<CharIndices>,
+    start: usize,
+    eof: ErrorKind,
+) -> Result<char, (usize, ErrorKind)> {
+    let c = match chars.next() {
+        Some((_, c)) => c,
+        None => return Err((0, eof)),
+    };
+    match c {
+        'n' => Ok('\n'),
+        't' => Ok('\t'),
+        'r' => Ok('\r'),
+        '0' => Ok('\0'),
+        '\\' | '"' | '\'' => Ok(c),
+        'x' => {
+            let mut value = 0;
+            for _ in 0..2 {
+                match chars.peek().and_then(|&(_, c)| c.to_digit(16)) {
+                    Some(digit) => value = value * 16 + digit,
+                    None => return Err((start, ErrorKind::MalformedHexEscape)),
+                }
+                chars.next();
+            }
+            Ok(char::from(value as u8))
+        }
+        'u' => {
+            if chars.next().map(|(_, c)| c) != Some('{') {
+                return Err((start, ErrorKind::MalformedUnicodeEscape));
+            }
+            let mut value: u32 = 0;
+            let mut digits = 0;
+            loop {
+                match chars.next() {
+                    Some((_, '}')) if digits > 0 => break,
+                    Some((_, c)) if digits < 6 && c.is_ascii_hexdigit() => {
+                        value = value * 16 + c.to_digit(16).unwrap();
+                        digits += 1;
+                    }
+                    _ => return Err((start, ErrorKind::MalformedUnicodeEscape)),
+                }
+            }
+            std::char::from_u32(value).ok_or((start, ErrorKind::InvalidUnicodeCodePoint(value)))
+        }
+        _ => Err((start, ErrorKind::UnknownEscape(c))),
+    }
 }
 
 struct FindTokenStartResult<'input> {
@@ -146,7 +273,9 @@ enum FindTokenStartState<'input> {
 pub struct Matcher<'input> {
     text: &'input str,
     location: Location,
-    failed: bool,
+    // Whether the last token could end an operand, so a following '-' is binary not unary
+    after_operand: bool,
+    after_unary_minus: bool,
 }
 
 impl<'input> Matcher<'input> {
@@ -154,7 +283,8 @@ impl<'input> Matcher<'input> {
         Matcher {
             text,
             location: Location::new(file, 1, 0, 0),
-            failed: false,
+            after_operand: false,
+            after_unary_minus: false,
         }
     }
 
@@ -211,20 +341,15 @@ impl<'input> Matcher<'input> {
                 '[' => wt!(RightBracket),
                 ';' => wt!(SemiColon),
                 ',' => wt!(Comma),
-                '&' => wt!(Ampersand),
-                '|' => wt!(Pipe),
-                '^' => wt!(Caret),
-                '+' => wt!(Plus),
-                '-' => wt!(Minus),
                 '~' => wt!(Tilde),
-                '*' => wt!(Asterisk),
-                '%' => wt!(Percent),
-                '/' => wt!(ForwardSlash),
-                '=' | '<' | '>' | '!' => result!(PunctuationStart),
+                '=' | '<' | '>' | '!' | '.' | ':' | '&' | '|' | '^' | '+' | '-' | '*' | '%'
+                | '/' => {
+                    result!(PunctuationStart)
+                }
                 '0'..='9' => result!(NumberStart),
                 '\'' => result!(CharStart),
                 '"' => result!(StringStart),
-                'a'..='z' | 'A'..='Z' | '_' => result!(IdentifierOrKeyWordStart),
+                c if is_identifier_start_char(c) => result!(IdentifierOrKeyWordStart),
                 _ => {
                     if in_comment {
                         self.location.line_offset_chars += 1
@@ -239,52 +364,111 @@ impl<'input> Matcher<'input> {
             state: EndOfFile,
         }
     }
+    /// Location of the byte `offset` into the remaining text, counting columns in chars.
+    fn location_at(&self, offset: usize) -> Location {
+        let mut location = self.location;
+        for c in self.text[..offset].chars() {
+            if c == '\n' {
+                location.new_line();
+            } else {
+                location.line_offset_chars += 1;
+            }
+        }
+        location.file_offset_bytes += offset;
+        location
+    }
     fn consume(&mut self, bytes: usize) {
-        self.location.file_offset_bytes += bytes;
-        self.location.line_offset_chars += bytes;
+        self.location = self.location_at(bytes);
         self.text = &self.text[bytes..];
     }
     fn token(&mut self, token: Tok<'input>, size: usize) -> <Self as Iterator>::Item {
+        use self::Tok::*;
+        self.after_unary_minus = token == Minus && !self.after_operand;
+        // N.B. LeftBracket is ']'
+        self.after_operand = matches!(
+            token,
+            Identifier(_) | Integer(_) | Char(_) | String(_) | RightParen | LeftBracket
+        );
         let start = self.location;
         self.consume(size);
         Ok((start, token, self.location))
     }
-    fn err(&mut self, kind: ErrorKind) -> <Self as Iterator>::Item {
-        self.failed = true;
-        Err(Error {
-            location: self.location,
-            kind,
-        })
+    /// Report an error at the current location, then resynchronise by skipping `skip` bytes.
+    fn err(&mut self, kind: ErrorKind, skip: usize) -> <Self as Iterator>::Item {
+        self.err_at(0, kind, skip)
+    }
+    fn err_at(&mut self, offset: usize, kind: ErrorKind, skip: usize) -> <Self as Iterator>::Item {
+        let location = self.location_at(offset);
+        self.consume(skip);
+        Err(Error { location, kind })
+    }
+    /// Length of a bad char or string literal, ending at its closing quote or the end of the line
+    /// if it has none.
+    fn bad_literal_len(&self) -> usize {
+        let mut chars = self.text.char_indices();
+        let quote = chars.next().map(|(_, c)| c);
+        while let Some((offset, c)) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some((offset, '\r')) | Some((offset, '\n')) => return offset,
+                    _ => {}
+                },
+                '\r' | '\n' => return offset,
+                c if Some(c) == quote => return offset + 1,
+                _ => {}
+            }
+        }
+        self.text.len()
     }
     fn extract_punctuation(&mut self) -> <Self as Iterator>::Item {
         use self::Tok::*;
         let mut chars = self.text.chars();
-        macro_rules! second_char {
+        // Extends the token matched so far if the next char is one of the given chars
+        macro_rules! next_char {
             ( $default:expr, $( $ch:tt => $tok:ident ),+ ) => {{
+                let len = self.text.len() - chars.as_str().len();
                 match chars.next() {
                     $(
-                        Some($ch) => self.token($tok, 2),
+                        Some($ch) => self.token($tok, len + 1),
                     )*
-                    _ => self.token($default, 1),
+                    _ => self.token($default, len),
                 }
             }};
         }
         match chars.next() {
             Some(char1) => match char1 {
-                '=' => second_char! {Equal,
+                '=' => next_char! {Equal,
                     '=' => DoubleEqual
                 },
-                '<' => second_char! {LessThan,
-                    '=' => LessThanOrEqual,
-                    '<' => LeftShift
+                '<' => match chars.next() {
+                    Some('=') => self.token(LessThanOrEqual, 2),
+                    Some('<') => next_char! {LeftShift, '=' => LeftShiftEqual},
+                    _ => self.token(LessThan, 1),
                 },
-                '>' => second_char!(MoreThan,
-                    '=' => MoreThanOrEqual,
-                    '>' => RightShift
-                ),
+                '>' => match chars.next() {
+                    Some('=') => self.token(MoreThanOrEqual, 2),
+                    Some('>') => next_char! {RightShift, '=' => RightShiftEqual},
+                    _ => self.token(MoreThan, 1),
+                },
+                '&' => next_char! {Ampersand, '=' => AmpersandEqual},
+                '|' => next_char! {Pipe, '=' => PipeEqual},
+                '^' => next_char! {Caret, '=' => CaretEqual},
+                '+' => next_char! {Plus, '=' => PlusEqual},
+                '-' => next_char! {Minus, '=' => MinusEqual},
+                '*' => next_char! {Asterisk, '=' => AsteriskEqual},
+                '%' => next_char! {Percent, '=' => PercentEqual},
+                '/' => next_char! {ForwardSlash, '=' => ForwardSlashEqual},
                 '!' => match chars.next() {
                     Some('=') => self.token(NotEqual, 2),
-                    _ => self.err(ErrorKind::LonelyExclamation),
+                    _ => self.err(ErrorKind::LonelyExclamation, 1),
+                },
+                '.' => match chars.next() {
+                    Some('.') => self.token(DotDot, 2),
+                    _ => self.err(ErrorKind::LonelyDot, 1),
+                },
+                ':' => match chars.next() {
+                    Some(':') => self.token(DoubleColon, 2),
+                    _ => self.err(ErrorKind::LonelyColon, 1),
                 },
                 _ => unreachable!(),
             },
@@ -292,38 +476,122 @@ impl<'input> Matcher<'input> {
         }
     }
     fn extract_number(&mut self) -> <Self as Iterator>::Item {
-        use std::str::FromStr;
-        let number_length = self
-            .text
-            .find(|c| !('0'..='9').contains(&c))
-            .unwrap_or(self.text.len());
-        let tok = Tok::Integer(i32::from_str(&self.text[..number_length]).unwrap());
-        self.token(tok, number_length)
+        let text = self.text;
+        let len = text
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(text.len());
+        let (radix, digits_start) = match text.get(..2) {
+            Some("0x") | Some("0X") => (16, 2),
+            Some("0o") | Some("0O") => (8, 2),
+            Some("0b") | Some("0B") => (2, 2),
+            _ => (10, 0),
+        };
+        // Decimal literals are signed, but the magnitude of i32::MIN is allowed straight after a
+        // unary minus. Other radixes give the bit pattern so may use all 32 bits.
+        let max_value = match radix {
+            10 if self.after_unary_minus => 1 << 31,
+            10 => i32::MAX as u64,
+            _ => u32::MAX as u64,
+        };
+        let mut value: u64 = 0;
+        let mut has_digits = false;
+        for (offset, c) in text[digits_start..len].char_indices() {
+            if c == '_' {
+                continue;
+            }
+            match c.to_digit(radix) {
+                Some(digit) => {
+                    value = value * u64::from(radix) + u64::from(digit);
+                    has_digits = true;
+                }
+                None => {
+                    return self.err_at(
+                        digits_start + offset,
+                        ErrorKind::InvalidDigitInIntegerLiteral(c),
+                        len,
+                    )
+                }
+            }
+            if value > max_value {
+                return self.err(ErrorKind::IntegerLiteralOutOfRange, len);
+            }
+        }
+        if !has_digits {
+            return self.err(ErrorKind::IntegerLiteralWithoutDigits, len);
+        }
+        self.token(Tok::Integer(value as u32 as i32), len)
     }
     fn extract_char(&mut self) -> <Self as Iterator>::Item {
-        let mut chars = self.text.chars();
-        if chars.next() != Some('\'') {
+        let text = self.text;
+        let mut chars = text.char_indices().peekable();
+        if chars.next() != Some((0, '\'')) {
             unreachable!();
         }
         let c = match chars.next() {
-            Some(c) => c,
-            None => return self.err(ErrorKind::EofInCharLiteral),
+            Some((offset, '\\')) => {
+                match extract_escape(&mut chars, offset, ErrorKind::EofInCharLiteral) {
+                    Ok(c) => c,
+                    Err((offset, kind)) => {
+                        let skip = self.bad_literal_len();
+                        return self.err_at(offset, kind, skip);
+                    }
+                }
+            }
+            Some((_, c)) => c,
+            None => return self.err(ErrorKind::EofInCharLiteral, text.len()),
         };
         match chars.next() {
-            Some('\'') => {}
-            Some(_) => return self.err(ErrorKind::BadCharLiteral),
-            None => return self.err(ErrorKind::EofInCharLiteral),
+            Some((offset, '\'')) => self.token(Tok::Char(c), offset + 1),
+            Some(_) => {
+                let skip = self.bad_literal_len();
+                self.err(ErrorKind::BadCharLiteral, skip)
+            }
+            None => self.err(ErrorKind::EofInCharLiteral, text.len()),
         }
-        let tok = Tok::Char(c);
-        self.token(tok, 3)
     }
     fn extract_string(&mut self) -> <Self as Iterator>::Item {
-        // TODO will break on unicode
-        if let Some(string_length) = self.text[1..].find('"') {
-            let s = &self.text[1..string_length + 1];
-            self.token(Tok::String(s), string_length + 2)
-        } else {
-            self.err(ErrorKind::EofInString)
+        let text = self.text;
+        let mut chars = text.char_indices().peekable();
+        if chars.next() != Some((0, '"')) {
+            unreachable!();
+        }
+        // Only allocate once we find an escape, otherwise borrow from the source
+        let mut unescaped: Option<String> = None;
+        loop {
+            match chars.next() {
+                Some((offset, '"')) => {
+                    let s = match unescaped {
+                        Some(s) => Cow::Owned(s),
+                        None => Cow::Borrowed(&text[1..offset]),
+                    };
+                    return self.token(Tok::String(s), offset + 1);
+                }
+                // Strings can't span lines, so close it at the end of this one
+                Some((offset, '\r')) | Some((offset, '\n')) => {
+                    return self.err(ErrorKind::EolInString, offset);
+                }
+                Some((offset, '\\'))
+                    if matches!(chars.peek(), Some(&(_, '\r')) | Some(&(_, '\n'))) =>
+                {
+                    return self.err(ErrorKind::EolInString, offset + 1);
+                }
+                Some((offset, '\\')) => {
+                    let s = unescaped.get_or_insert_with(|| text[1..offset].to_string());
+                    match extract_escape(&mut chars, offset, ErrorKind::EofInString) {
+                        Ok(c) => s.push(c),
+                        Err((offset, kind)) => {
+                            let skip = self.bad_literal_len();
+                            return self.err_at(offset, kind, skip);
+                        }
+                    }
+                }
+                Some((_, c)) => {
+                    if let Some(ref mut s) = unescaped {
+                        s.push(c);
+                    }
+                }
+                None => return self.err(ErrorKind::EofInString, text.len()),
+            }
         }
     }
     fn extract_identifier_or_keyword(&mut self) -> <Self as Iterator>::Item {
@@ -332,11 +600,20 @@ impl<'input> Matcher<'input> {
             .find(|c| !is_identifier_char(c))
             .unwrap_or(self.text.len());
         let tok = match &self.text[..len] {
+            "import" => Tok::Import,
+            "pub" => Tok::Pub,
             "function" => Tok::Function,
             "return" => Tok::Return,
             "let" => Tok::Let,
+            "const" => Tok::Const,
+            "global" => Tok::Global,
             "if" => Tok::If,
+            "else" => Tok::Else,
             "while" => Tok::While,
+            "for" => Tok::For,
+            "in" => Tok::In,
+            "break" => Tok::Break,
+            "continue" => Tok::Continue,
             "and" => Tok::And,
             "not" => Tok::Not,
             "or" => Tok::Or,
@@ -351,9 +628,6 @@ impl<'input> Iterator for Matcher<'input> {
 
     fn next(&mut self) -> Option<Self::Item> {
         use self::FindTokenStartState::*;
-        if self.failed {
-            return None;
-        }
         let token_start = self.find_token_start();
         self.location.file_offset_bytes += token_start.offset;
         self.text = &self.text[token_start.offset..];
@@ -364,7 +638,10 @@ impl<'input> Iterator for Matcher<'input> {
             CharStart => Some(self.extract_char()),
             StringStart => Some(self.extract_string()),
             IdentifierOrKeyWordStart => Some(self.extract_identifier_or_keyword()),
-            Err(e) => Some(self.err(e)),
+            Err(e) => {
+                let skip = self.text.chars().next().map_or(0, char::len_utf8);
+                Some(self.err(e, skip))
+            }
             EndOfFile => None,
         }
     }
@@ -408,6 +685,17 @@ mod tests {
         };
     }
 
+    macro_rules! test_errs {
+        ( $test_name:ident, $input:expr, $expected:expr) => {
+            #[test]
+            fn $test_name() {
+                let matcher = Matcher::new(FileHandle::dummy(), $input);
+                let errs = matcher.filter_map(|x| x.err()).collect::<Vec<_>>();
+                assert_eq!(errs, $expected);
+            }
+        };
+    }
+
     fn tok(
         t: Tok,
         line: usize,
@@ -432,6 +720,31 @@ mod tests {
         ))
     }
 
+    fn tok_unicode(
+        t: Tok,
+        line: usize,
+        start_line_offset: usize,
+        start_file_offset: usize,
+        chars: usize,
+        bytes: usize,
+    ) -> <Matcher as Iterator>::Item {
+        Ok((
+            Location::new(
+                FileHandle::dummy(),
+                line,
+                start_line_offset,
+                start_file_offset,
+            ),
+            t,
+            Location::new(
+                FileHandle::dummy(),
+                line,
+                start_line_offset + chars,
+                start_file_offset + bytes,
+            ),
+        ))
+    }
+
     fn err(kind: ErrorKind, line: usize, line_offset: usize, file_offset: usize) -> Error {
         Error {
             location: Location::new(FileHandle::dummy(), line, line_offset, file_offset),
@@ -478,9 +791,46 @@ mod tests {
     test_lex! {extract_right_shift, ">>", vec![
         tok(RightShift, 1, 0, 0, 2),
     ]}
+    test_lex! {extract_left_shift_equal, "<<=", vec![
+        tok(LeftShiftEqual, 1, 0, 0, 3),
+    ]}
+    test_lex! {extract_right_shift_equal, ">>=", vec![
+        tok(RightShiftEqual, 1, 0, 0, 3),
+    ]}
+    test_lex! {extract_compound_assignment_operators, "+= -= *= /= %= &= |= ^=", vec![
+        tok(PlusEqual, 1, 0, 0, 2),
+        tok(MinusEqual, 1, 3, 3, 2),
+        tok(AsteriskEqual, 1, 6, 6, 2),
+        tok(ForwardSlashEqual, 1, 9, 9, 2),
+        tok(PercentEqual, 1, 12, 12, 2),
+        tok(AmpersandEqual, 1, 15, 15, 2),
+        tok(PipeEqual, 1, 18, 18, 2),
+        tok(CaretEqual, 1, 21, 21, 2),
+    ]}
+    test_lex! {extract_minus_before_equals, "a-=-1", vec![
+        tok(Identifier("a"), 1, 0, 0, 1),
+        tok(MinusEqual, 1, 1, 1, 2),
+        tok(Minus, 1, 3, 3, 1),
+        tok(Integer(1), 1, 4, 4, 1),
+    ]}
     test_lex! {extract_not_equal, "!=", vec![
         tok(NotEqual, 1, 0, 0, 2),
     ]}
+    test_lex! {extract_range, "0..len(a)", vec![
+        tok(Integer(0), 1, 0, 0, 1),
+        tok(DotDot, 1, 1, 1, 2),
+        tok(Identifier("len"), 1, 3, 3, 3),
+        tok(LeftParen, 1, 6, 6, 1),
+        tok(Identifier("a"), 1, 7, 7, 1),
+        tok(RightParen, 1, 8, 8, 1),
+    ]}
+    test_lex! {extract_qualified_call, "m::f()", vec![
+        tok(Identifier("m"), 1, 0, 0, 1),
+        tok(DoubleColon, 1, 1, 1, 2),
+        tok(Identifier("f"), 1, 3, 3, 1),
+        tok(LeftParen, 1, 4, 4, 1),
+        tok(RightParen, 1, 5, 5, 1),
+    ]}
     test_lex! {extract_integer, "123", vec![
         tok(Integer(123), 1, 0, 0, 3),
     ]}
@@ -489,21 +839,78 @@ mod tests {
         tok(Minus, 1, 4, 4, 1),
         tok(Integer(3), 1, 6, 6, 2),
     ]}
+    test_lex! {extract_hex_integer, "0xfF", vec![
+        tok(Integer(255), 1, 0, 0, 4),
+    ]}
+    test_lex! {extract_binary_integer, "0b1010", vec![
+        tok(Integer(10), 1, 0, 0, 6),
+    ]}
+    test_lex! {extract_octal_integer, "0o17", vec![
+        tok(Integer(15), 1, 0, 0, 4),
+    ]}
+    test_lex! {extract_integer_with_digit_separators, "1_000_000 0x_ff_ff", vec![
+        tok(Integer(1_000_000), 1, 0, 0, 9),
+        tok(Integer(0xffff), 1, 10, 10, 8),
+    ]}
+    test_lex! {extract_max_integer, "2147483647", vec![
+        tok(Integer(i32::MAX), 1, 0, 0, 10),
+    ]}
+    test_lex! {extract_hex_integer_uses_all_bits, "0xFFFFFFFF", vec![
+        tok(Integer(-1), 1, 0, 0, 10),
+    ]}
+    test_lex! {extract_min_integer_after_unary_minus, "(-2147483648", vec![
+        tok(LeftParen, 1, 0, 0, 1),
+        tok(Minus, 1, 1, 1, 1),
+        tok(Integer(i32::MIN), 1, 2, 2, 10),
+    ]}
     test_lex! {extract_char, "'s'", vec![
         tok(Char('s'), 1, 0, 0, 3),
     ]}
     test_lex! {extract_str, r#""hello""#, vec![
-        tok(String("hello"), 1, 0, 0, 7),
+        tok(String("hello".into()), 1, 0, 0, 7),
+    ]}
+    test_lex! {extract_char_escapes, r#"'\n' '\t' '\r' '\0' '\\' '\'' '\"'"#, vec![
+        tok(Char('\n'), 1, 0, 0, 4),
+        tok(Char('\t'), 1, 5, 5, 4),
+        tok(Char('\r'), 1, 10, 10, 4),
+        tok(Char('\0'), 1, 15, 15, 4),
+        tok(Char('\\'), 1, 20, 20, 4),
+        tok(Char('\''), 1, 25, 25, 4),
+        tok(Char('"'), 1, 30, 30, 4),
+    ]}
+    test_lex! {extract_char_hex_escape, r"'\x7f'", vec![
+        tok(Char('\x7f'), 1, 0, 0, 6),
+    ]}
+    test_lex! {extract_char_hex_escape_above_ascii, r"'\xFF'", vec![
+        tok(Char('\u{ff}'), 1, 0, 0, 6),
+    ]}
+    test_lex! {extract_char_unicode_escape, r"'\u{1F600}'", vec![
+        tok(Char('\u{1F600}'), 1, 0, 0, 11),
+    ]}
+    test_lex! {extract_str_with_escapes, r#""a\tb\n\"c\"\\""#, vec![
+        tok(String("a\tb\n\"c\"\\".into()), 1, 0, 0, 15),
+    ]}
+    test_lex! {extract_str_with_hex_and_unicode_escapes, r#""\x41\u{42}""#, vec![
+        tok(String("AB".into()), 1, 0, 0, 12),
     ]}
     test_lex! {extract_identifier, "bob", vec![
         tok(Identifier("bob"), 1, 0, 0, 3),
     ]}
     test_keywords! {
+        extract_import: "import" => Import,
+        extract_pub: "pub" => Pub,
         extract_function: "function" => Function,
         extract_return: "return" => Return,
         extract_let: "let" => Let,
+        extract_const: "const" => Const,
+        extract_global: "global" => Global,
         extract_if: "if" => If,
+        extract_else: "else" => Else,
         extract_while: "while" => While,
+        extract_for: "for" => For,
+        extract_in: "in" => In,
+        extract_break: "break" => Break,
+        extract_continue: "continue" => Continue,
         extract_and: "and" => And,
         extract_not: "not" => Not,
         extract_or: "or" => Or
@@ -544,22 +951,103 @@ mod tests {
         tok(If, 1, 0, 0, 2),
         tok(While, 2, 0, 4, 5),
     ]}
+    test_lex! {extract_unicode_char, "'é' '€' '😀' +", vec![
+        tok_unicode(Char('é'), 1, 0, 0, 3, 4),
+        tok_unicode(Char('€'), 1, 4, 5, 3, 5),
+        tok_unicode(Char('😀'), 1, 8, 11, 3, 6),
+        tok(Plus, 1, 12, 18, 1),
+    ]}
+    test_lex! {extract_unicode_str, r#""héllo wörld" +"#, vec![
+        tok_unicode(String("héllo wörld".into()), 1, 0, 0, 13, 15),
+        tok(Plus, 1, 14, 16, 1),
+    ]}
+    test_lex! {extract_unicode_identifier, "café_1 = ñ", vec![
+        tok_unicode(Identifier("café_1"), 1, 0, 0, 6, 7),
+        tok(Equal, 1, 7, 8, 1),
+        tok_unicode(Identifier("ñ"), 1, 9, 10, 1, 2),
+    ]}
+    test_lex! {unicode_comments, "# ünïcödé ✓\n+ # →\n+", vec![
+        tok(Plus, 2, 0, 18, 1),
+        tok(Plus, 3, 0, 26, 1),
+    ]}
+    test_lex! {str_with_line_break_updates_line, "\"a\nb +", vec![
+        Err(err(EolInString, 1, 0, 0)),
+        tok(Identifier("b"), 2, 0, 3, 1),
+        tok(Plus, 2, 2, 5, 1),
+    ]}
 
     #[test]
-    fn terminates_after_error() {
+    fn continues_after_error() {
         let matcher = Matcher::new(FileHandle::dummy(), "if $ {");
-        let output = matcher.take(3).collect::<Vec<_>>();
+        let output = matcher.collect::<Vec<_>>();
         assert_eq!(
             output,
-            vec![tok(If, 1, 0, 0, 2), Err(err(IllegalChar('$'), 1, 3, 3)),]
+            vec![
+                tok(If, 1, 0, 0, 2),
+                Err(err(IllegalChar('$'), 1, 3, 3)),
+                tok(LeftBrace, 1, 5, 5, 1),
+            ]
         )
     }
 
+    test_errs! {collects_every_error, "a $ b ! c € d", vec![
+        err(IllegalChar('$'), 1, 2, 2),
+        err(LonelyExclamation, 1, 6, 6),
+        err(IllegalChar('€'), 1, 10, 10),
+    ]}
+    test_errs! {recovers_after_bad_literals, "'ab' 0b12 \"\\q\" 99999999999 '\\y'", vec![
+        err(BadCharLiteral, 1, 0, 0),
+        err(InvalidDigitInIntegerLiteral('2'), 1, 8, 8),
+        err(UnknownEscape('q'), 1, 11, 11),
+        err(IntegerLiteralOutOfRange, 1, 15, 15),
+        err(UnknownEscape('y'), 1, 28, 28),
+    ]}
+    test_lex! {closes_unterminated_string_at_end_of_line, "\"abc\r\n+", vec![
+        Err(err(EolInString, 1, 0, 0)),
+        tok(Plus, 2, 0, 6, 1),
+    ]}
+    test_lex! {closes_string_at_escaped_line_break, "\"a\\\n+", vec![
+        Err(err(EolInString, 1, 0, 0)),
+        tok(Plus, 2, 0, 4, 1),
+    ]}
+    test_errs! {unterminated_string_stops_before_later_quote, "let a = \"abc;\nlet b = 1 $ 2;\nlet c = \"x\";", vec![
+        err(EolInString, 1, 8, 8),
+        err(IllegalChar('$'), 2, 10, 24),
+    ]}
+    test_lex! {continues_after_bad_char_literal, "'ab' +", vec![
+        Err(err(BadCharLiteral, 1, 0, 0)),
+        tok(Plus, 1, 5, 5, 1),
+    ]}
+    test_lex! {continues_after_bad_escape_in_string, r#""a\qb\"c" +"#, vec![
+        Err(err(UnknownEscape('q'), 1, 2, 2)),
+        tok(Plus, 1, 10, 10, 1),
+    ]}
+
     test_err! {return_illegal_char, "id$", err(IllegalChar('$'), 1, 2, 2)}
     test_err! {return_lonely_exclamation, "if ! a", err(LonelyExclamation, 1, 3, 3)}
+    test_err! {return_lonely_dot, "a.b", err(LonelyDot, 1, 1, 1)}
+    test_err! {return_lonely_colon, "a:b", err(LonelyColon, 1, 1, 1)}
     test_err! {return_eof_in_char_early, "if '", err(EofInCharLiteral, 1, 3, 3)}
     test_err! {return_eof_in_char_late, "if 'a", err(EofInCharLiteral, 1, 3, 3)}
     test_err! {return_bad_char_literal, "if 'as' {", err(BadCharLiteral, 1, 3, 3)}
     test_err! {return_eof_in_string, r#"let a = "seffsd"#, err(EofInString, 1, 8, 8)}
+    test_err! {return_unknown_escape_in_string, r#"let a = "ab\q""#, err(UnknownEscape('q'), 1, 11, 11)}
+    test_err! {return_unknown_escape_in_char, r"if '\a'", err(UnknownEscape('a'), 1, 4, 4)}
+    test_err! {return_eof_in_escape, r#"let a = "ab\"#, err(EofInString, 1, 8, 8)}
+    test_err! {return_malformed_hex_escape, r#"a = "\x4g""#, err(MalformedHexEscape, 1, 5, 5)}
+    test_err! {return_malformed_unicode_escape, r#"a = "\u41""#, err(MalformedUnicodeEscape, 1, 5, 5)}
+    test_err! {return_oversized_unicode_escape, r#"a = "\u{1234567}""#, err(MalformedUnicodeEscape, 1, 5, 5)}
+    test_err! {return_invalid_unicode_code_point, r#"a = "\u{D800}""#,
+        err(InvalidUnicodeCodePoint(0xD800), 1, 5, 5)}
+    test_err! {return_bad_escape_on_later_line, "a = 1;\nb = \"y\\z\"", err(UnknownEscape('z'), 2, 6, 13)}
+    test_err! {return_decimal_integer_out_of_range, "a = 99999999999;", err(IntegerLiteralOutOfRange, 1, 4, 4)}
+    test_err! {return_min_integer_without_minus, "2147483648", err(IntegerLiteralOutOfRange, 1, 0, 0)}
+    test_err! {return_min_integer_after_binary_minus, "a - 2147483648", err(IntegerLiteralOutOfRange, 1, 4, 4)}
+    test_err! {return_hex_integer_out_of_range, "0x1_0000_0000", err(IntegerLiteralOutOfRange, 1, 0, 0)}
+    test_err! {return_invalid_digit_in_binary, "0b0120", err(InvalidDigitInIntegerLiteral('2'), 1, 4, 4)}
+    test_err! {return_invalid_digit_in_decimal, "12ab", err(InvalidDigitInIntegerLiteral('a'), 1, 2, 2)}
+    test_err! {return_integer_without_digits, "0x_", err(IntegerLiteralWithoutDigits, 1, 0, 0)}
+    test_err! {return_illegal_unicode_char, "a = é€", err(IllegalChar('€'), 1, 5, 6)}
+    test_err! {return_bad_escape_after_unicode, r#""ü\q""#, err(UnknownEscape('q'), 1, 2, 3)}
     test_err! {return_illegal_character_return, "if\r{", err(MisPlacedCharacterReturn, 1, 2, 2)}
 }
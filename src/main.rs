@@ -1,5 +1,7 @@
-use std::io;
+use std::cell::RefCell;
+use std::io::{self, Write};
 use std::process::exit;
+use std::rc::Rc;
 
 extern crate ansi_term;
 extern crate lalrpop_util;
@@ -8,7 +10,9 @@ extern crate lalrpop_util;
 extern crate indoc;
 
 extern crate argparse;
-use argparse::{ArgumentParser, Collect, Store};
+use argparse::action::{Action, IArgAction, ParseResult, TypedAction};
+use argparse::{ArgumentParser, Collect, Store, StoreTrue};
+use exec_tree::lints::{Lint, LintLevel, LINTS};
 
 pub mod ast;
 #[rustfmt::skip]
@@ -23,14 +27,46 @@ use error::*;
 use file_data::{FileData, FileHandle};
 
 mod file_data;
+mod imports;
 
 #[cfg(test)]
 mod test_grammar;
 
+/// Lint flags are kept in the order given, so that a later flag for a lint overrides an earlier one
+type LintFlags = Vec<(LintLevel, String)>;
+
+/// Collects the lints named by a flag along with the flag's level
+struct CollectLint(LintLevel);
+
+struct CollectLintAction<'a> {
+    level: LintLevel,
+    cell: Rc<RefCell<&'a mut LintFlags>>,
+}
+
+impl TypedAction<LintFlags> for CollectLint {
+    fn bind<'x>(&self, cell: Rc<RefCell<&'x mut LintFlags>>) -> Action<'x> {
+        // Single actions are run as they're parsed, unlike collections which are grouped by flag
+        Action::Single(Box::new(CollectLintAction {
+            level: self.0,
+            cell,
+        }))
+    }
+}
+
+impl<'a> IArgAction for CollectLintAction<'a> {
+    fn parse_arg(&self, arg: &str) -> ParseResult {
+        self.cell.borrow_mut().push((self.level, arg.to_string()));
+        ParseResult::Parsed
+    }
+}
+
 fn main() {
     let mut stdlib_path = "stdlib.sl".to_string();
     let mut script_path = String::new();
+    let mut import_paths: Vec<String> = Vec::new();
     let mut args: Vec<String> = Vec::new();
+    let mut options = exec_tree::Options::default();
+    let mut lint_flags = LintFlags::new();
     {
         let mut parser = ArgumentParser::new();
         parser.set_description("Mike's first StarLang iterpreter using an executable AST");
@@ -39,6 +75,44 @@ fn main() {
             Store,
             "path to the standard library to load. Default stdlib.sl",
         );
+        parser.refer(&mut import_paths).add_option(
+            &["-I", "--import-path"],
+            Collect,
+            "directory to search for imports that aren't found relative to the importing file",
+        );
+        parser.refer(&mut options.infer_types).add_option(
+            &["--infer-types"],
+            StoreTrue,
+            "report operations that will always fail because of an int or array's type",
+        );
+        parser
+            .refer(&mut lint_flags)
+            .add_option(
+                &["-A", "--allow"],
+                CollectLint(LintLevel::Allow),
+                "lint to ignore",
+            )
+            .add_option(
+                &["-W", "--warn"],
+                CollectLint(LintLevel::Warn),
+                "lint to report without stopping the script from running. The default for all \
+                 lints",
+            )
+            .add_option(
+                &["-D", "--deny"],
+                CollectLint(LintLevel::Deny),
+                "lint to report as an error. When a lint is given more than once the last wins",
+            );
+        parser.refer(&mut options.checked_arithmetic).add_option(
+            &["--checked-arithmetic"],
+            StoreTrue,
+            "report integer overflow as an error instead of wrapping",
+        );
+        parser.refer(&mut options.entry_point).add_option(
+            &["--entry-point"],
+            Store,
+            "function in the script to start from. Default main",
+        );
         parser.refer(&mut script_path).add_argument(
             "script_path",
             Store,
@@ -49,8 +123,22 @@ fn main() {
             .add_argument("args", Collect, "The args passed to the script");
         parser.parse_args_or_exit()
     }
+    for (level, name) in lint_flags {
+        match Lint::from_name(&name) {
+            Some(lint) => options.lints.set(lint, level),
+            None => {
+                let known = LINTS.iter().map(|lint| lint.name()).collect::<Vec<_>>();
+                eprintln!(
+                    "Unknown lint {:?}, expected one of: {}",
+                    name,
+                    known.join(", ")
+                );
+                exit(2);
+            }
+        }
+    }
     args.insert(0, script_path.clone());
-    let exit_status = match run(stdlib_path, script_path, args) {
+    let exit_status = match run(stdlib_path, script_path, &import_paths, &options, args) {
         Ok(n) => n,
         Err(OuterError::FailedInitAnsiTerm(err_code)) => {
             eprintln!(
@@ -64,20 +152,46 @@ fn main() {
     exit(exit_status);
 }
 
-fn run(stdlib_path: String, script_path: String, args: Vec<String>) -> OuterResult<i32> {
+fn run(
+    stdlib_path: String,
+    script_path: String,
+    import_paths: &[String],
+    options: &exec_tree::Options,
+    args: Vec<String>,
+) -> OuterResult<i32> {
     let _ = ansi_term::enable_ansi_support(); //.map_err(|e| OuterError::FailedInitAnsiTerm(e))?;
     let mut files = FileData::new();
-    let stdlib_handle = files.read(stdlib_path)?;
-    let script_handle = files.read(script_path)?;
+    // The stdlib is the prelude, so the script and its imports can use it without importing it
+    let loaded = imports::load_files(&mut files, vec![stdlib_path, script_path], import_paths)?;
+    if !loaded.errors.is_empty() {
+        let stderr = io::stderr();
+        write_import_errors(&mut stderr.lock(), &loaded.errors, &files)?;
+        return Err(OuterError::ImportFailed);
+    }
     {
-        let mut programme = parse_file(stdlib_handle, &files)?;
-        programme.extend(parse_file(script_handle, &files)?);
+        let programme = parse_files(&loaded, &files, options)?;
         let stdin = io::stdin();
         let stdout = io::stdout();
         {
             let mut stdin_lock = stdin.lock();
             let mut stdout_lock = stdout.lock();
-            match exec_tree::exec(&programme, args, &mut stdin_lock, &mut stdout_lock) {
+            // Written before the programme runs, so they come before anything it outputs
+            let mut warned = Ok(());
+            let result = exec_tree::exec(
+                &programme,
+                options,
+                args,
+                &mut stdin_lock,
+                &mut stdout_lock,
+                &mut |warnings| {
+                    if !warnings.is_empty() {
+                        let stderr = io::stderr();
+                        warned = write_warnings(&mut stderr.lock(), &warnings, &files);
+                    }
+                },
+            );
+            warned?;
+            match result {
                 Err(err) => {
                     let stderr = io::stderr();
                     write_exec_error(&mut stderr.lock(), &err, &files)?;
@@ -89,14 +203,70 @@ fn run(stdlib_path: String, script_path: String, args: Vec<String>) -> OuterResu
     }
 }
 
-fn parse_file(file: FileHandle, files: &FileData) -> OuterResult<Vec<ast::Function<'_>>> {
-    let lexer = Matcher::new(file, files.get_contents(file));
-    match grammar::parse_Programme(lexer) {
-        Ok(rv) => Ok(rv),
+/// Parse the files into a module each, with the first root as the prelude. If any fail to parse
+/// then the errors are reported and, provided the parser could recover, static analysis is run on
+/// whatever did parse.
+fn parse_files<'a>(
+    loaded: &imports::LoadedFiles,
+    files: &'a FileData,
+    options: &exec_tree::Options,
+) -> OuterResult<Vec<ast::Module<'a>>> {
+    let mut programme = Some(Vec::new());
+    let mut parse_errs = Vec::new();
+    for file in &loaded.files {
+        let (items, errs) = parse_programme(file.handle, files.get_contents(file.handle));
+        parse_errs.extend(errs);
+        programme = programme.and_then(|mut programme| {
+            programme.push(ast::Module {
+                file: file.handle,
+                name: imports::module_name(files.get_name(file.handle)),
+                imports: file.imports.clone(),
+                prelude: loaded.roots.first() == Some(&file.handle),
+                items: items?,
+            });
+            Some(programme)
+        });
+    }
+    if parse_errs.is_empty() {
+        return Ok(programme.expect("Parsed without errors"));
+    }
+    let stderr = io::stderr();
+    let mut stderr_lock = stderr.lock();
+    write_parse_error(&mut stderr_lock, parse_errs, files)?;
+    if let Some(programme) = programme {
+        // Warnings aren't shown for programmes with syntax errors
+        if let Err(err) = exec_tree::check(&programme, options, &mut |_| {}) {
+            writeln!(stderr_lock).map_err(|_| OuterError::OutputError)?;
+            write_exec_error(&mut stderr_lock, &err, files)?;
+        }
+    }
+    Err(OuterError::ParseError)
+}
+
+/// Parse a file, recovering from syntax errors where possible. Returns the items if the
+/// file could be parsed at all along with every error found. The parser stops at the first
+/// lexical error, but lexing carries on to report every lexical error in the file.
+fn parse_programme(
+    file: FileHandle,
+    text: &str,
+) -> (Option<Vec<ast::Item<'_>>>, Vec<ParseError<'_>>) {
+    let mut lexer = Matcher::new(file, text);
+    let mut recovered = Vec::new();
+    let result = grammar::parse_Programme(&mut recovered, &mut lexer);
+    let mut errs = recovered
+        .into_iter()
+        .map(|recovery| recovery.error)
+        .collect::<Vec<_>>();
+    match result {
+        Ok(rv) => (Some(rv), errs),
         Err(err) => {
-            let stderr = io::stderr();
-            write_parse_error(&mut stderr.lock(), err, files)?;
-            Err(OuterError::ParseError)
+            errs.push(err);
+            errs.extend(
+                lexer
+                    .filter_map(|item| item.err())
+                    .map(|error| ParseError::User { error }),
+            );
+            (None, errs)
         }
     }
 }
@@ -7,7 +7,8 @@ macro_rules! test_expr {
         assert_eq!(
             &format!(
                 "{:?}",
-                grammar::parse_Expr(Matcher::new(FileHandle::dummy(), $text)).unwrap()
+                grammar::parse_Expr(&mut Vec::new(), Matcher::new(FileHandle::dummy(), $text))
+                    .unwrap()
             ),
             $ast
         )
@@ -19,7 +20,8 @@ macro_rules! test_stmt {
         assert_eq!(
             &format!(
                 "{:?}",
-                grammar::parse_Statement(Matcher::new(FileHandle::dummy(), $text)).unwrap()
+                grammar::parse_Statement(&mut Vec::new(), Matcher::new(FileHandle::dummy(), $text))
+                    .unwrap()
             ),
             $ast
         )
@@ -74,7 +76,11 @@ fn comparision_op() {
 
 #[test]
 fn chained_comparision_op_not_allowed() {
-    assert!(grammar::parse_Expr(Matcher::new(FileHandle::dummy(), "3 < 4 < 5")).is_err())
+    assert!(grammar::parse_Expr(
+        &mut Vec::new(),
+        Matcher::new(FileHandle::dummy(), "3 < 4 < 5")
+    )
+    .is_err())
 }
 
 #[test]
@@ -111,6 +117,14 @@ fn call() {
     )
 }
 
+#[test]
+fn qualified_call() {
+    test_expr!(
+        "array::push(a, 1)",
+        "Call(function: array::push, arguments: [Identifier(a), 1])"
+    )
+}
+
 #[test]
 fn identifier_expr() {
     test_expr!("3 + var1", "BinaryOp(3 + Identifier(var1))")
@@ -139,6 +153,16 @@ fn string_literal() {
     test_expr!("\"str\"", "String(\"str\")")
 }
 
+#[test]
+fn escaped_character_literal() {
+    test_expr!(r"'\n'", r"Char('\n')")
+}
+
+#[test]
+fn escaped_string_literal() {
+    test_expr!(r#""a\tb\"""#, r#"String("a\tb\"")"#)
+}
+
 #[test]
 fn return_statement() {
     test_stmt!("return 4;", "Return(4)")
@@ -160,6 +184,16 @@ fn assignment_statement() {
     )
 }
 
+#[test]
+fn compound_assignment_statement() {
+    test_stmt!(
+        "a[i] <<= 2 + b;",
+        "CompoundAssign(\
+         target: Subscription(array_expr: Identifier(a), subscript_expr: Identifier(i)), \
+         op: <<, expr: BinaryOp(2 + Identifier(b)))"
+    )
+}
+
 #[test]
 fn declaration_statement() {
     test_stmt!("let var = 3;", "Declare(identifier: var, expr: 3)")
@@ -175,6 +209,38 @@ fn if_statemenet() {
     )
 }
 
+#[test]
+fn if_else_statemenet() {
+    test_stmt!(
+        "if a == 2 {
+            f();
+        } else {
+            g();
+        }",
+        "If(test: BinaryOp(Identifier(a) == 2), \
+         block: [Expr(Call(function: f, arguments: []))], \
+         else: [Expr(Call(function: g, arguments: []))])"
+    )
+}
+
+#[test]
+fn else_if_statemenet() {
+    test_stmt!(
+        "if a == 2 {
+            f();
+        } else if a == 3 {
+            g();
+        } else {
+            h();
+        }",
+        "If(test: BinaryOp(Identifier(a) == 2), \
+         block: [Expr(Call(function: f, arguments: []))], \
+         else: [If(test: BinaryOp(Identifier(a) == 3), \
+         block: [Expr(Call(function: g, arguments: []))], \
+         else: [Expr(Call(function: h, arguments: []))])])"
+    )
+}
+
 #[test]
 fn while_statemenet() {
     test_stmt!(
@@ -185,6 +251,41 @@ fn while_statemenet() {
     )
 }
 
+#[test]
+fn for_range_statemenet() {
+    test_stmt!(
+        "for i in 0..len(a) {
+            f(i);
+        }",
+        "ForRange(variable: i, start: 0, end: Call(function: len, arguments: [Identifier(a)]), \
+         block: [Expr(Call(function: f, arguments: [Identifier(i)]))])"
+    )
+}
+
+#[test]
+fn for_each_statemenet() {
+    test_stmt!(
+        "for c in \"abc\" {
+            f(c);
+        }",
+        "ForEach(variable: c, array: String(\"abc\"), \
+         block: [Expr(Call(function: f, arguments: [Identifier(c)]))])"
+    )
+}
+
+#[test]
+fn break_and_continue_statements() {
+    test_stmt!(
+        "while 1 {
+            if a {
+                break;
+            }
+            continue;
+        }",
+        "While(test: 1, block: [If(test: Identifier(a), block: [Break]), Continue])"
+    )
+}
+
 #[test]
 fn function_definition() {
     let text = "\
@@ -194,12 +295,23 @@ fn function_definition() {
     ";
     let actual = &format!(
         "{:?}",
-        grammar::parse_Function(Matcher::new(FileHandle::dummy(), text)).unwrap()
+        grammar::parse_Function(&mut Vec::new(), Matcher::new(FileHandle::dummy(), text)).unwrap()
     );
     let expected = "Function(name: fname, arguments: [arg1], stmts: [Return(1)])";
     assert_eq!(actual, expected);
 }
 
+#[test]
+fn pub_function() {
+    let text = "pub function fname() {}";
+    let actual = &format!(
+        "{:?}",
+        grammar::parse_Function(&mut Vec::new(), Matcher::new(FileHandle::dummy(), text)).unwrap()
+    );
+    let expected = "pub Function(name: fname, arguments: [], stmts: [])";
+    assert_eq!(actual, expected);
+}
+
 #[test]
 fn programme() {
     let text = "\
@@ -214,7 +326,7 @@ fn programme() {
     ";
     let actual = &format!(
         "{:?}",
-        grammar::parse_Programme(Matcher::new(FileHandle::dummy(), text)).unwrap()
+        grammar::parse_Programme(&mut Vec::new(), Matcher::new(FileHandle::dummy(), text)).unwrap()
     );
     let expected = "[\
                     Function(name: fname, arguments: [arg1], stmts: [Return(1)]), \
@@ -227,3 +339,80 @@ fn programme() {
                     ])]";
     assert_eq!(actual, expected);
 }
+
+#[test]
+fn programme_recovers_from_errors_at_statement_boundaries() {
+    let text = "\
+        function fname() {
+            let a = 1 2;
+            f(a)
+        }
+
+        function main(args) {
+            return 1;
+        }
+    ";
+    let mut errors = Vec::new();
+    let actual = &format!(
+        "{:?}",
+        grammar::parse_Programme(&mut errors, Matcher::new(FileHandle::dummy(), text)).unwrap()
+    );
+    let expected = "[\
+                    Function(name: fname, arguments: [], stmts: [Expr(error), Expr(error)]), \
+                    Function(name: main, arguments: [args], stmts: [Return(1)])\
+                    ]";
+    assert_eq!(actual, expected);
+    assert_eq!(errors.len(), 2);
+}
+
+#[test]
+fn programme_with_globals() {
+    let text = "\
+        const SIZE = 4096;
+        global count = SIZE - 1;
+
+        function main(args) {
+            return count;
+        }
+    ";
+    let actual = &format!(
+        "{:?}",
+        grammar::parse_Programme(&mut Vec::new(), Matcher::new(FileHandle::dummy(), text)).unwrap()
+    );
+    let expected = "[\
+                    Const(name: SIZE, expr: 4096), \
+                    Global(name: count, expr: BinaryOp(Identifier(SIZE) - 1)), \
+                    Function(name: main, arguments: [args], stmts: [Return(Identifier(count))])\
+                    ]";
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn programme_with_imports() {
+    let text = "\
+        import \"lib/util.sl\";
+        import \"other.sl\";
+
+        function main(args) {
+        }
+    ";
+    let actual = &format!(
+        "{:?}",
+        grammar::parse_Programme(&mut Vec::new(), Matcher::new(FileHandle::dummy(), text)).unwrap()
+    );
+    // The imports are loaded before parsing, so they aren't kept
+    let expected = "[Function(name: main, arguments: [args], stmts: [])]";
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn imports_must_come_first() {
+    let text = "\
+        function main(args) {
+        }
+        import \"util.sl\";
+    ";
+    assert!(
+        grammar::parse_Programme(&mut Vec::new(), Matcher::new(FileHandle::dummy(), text)).is_err()
+    );
+}